@@ -374,24 +374,61 @@ export function getMarketPDA(
   );
 }
 
-export function getUserPositionPDA(
+/**
+ * Global counter; the next `create_market` uses its nextMarketId
+ */
+export function getMarketCounterPDA(programId: PublicKey): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('market_counter')],
+    programId
+  );
+}
+
+export function getUserMarketPDA(
   programId: PublicKey,
   userPubkey: PublicKey,
   marketPubkey: PublicKey
+): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync(
+    [
+      Buffer.from('user_market'),
+      userPubkey.toBuffer(),
+      marketPubkey.toBuffer(),
+    ],
+    programId
+  );
+}
+
+/**
+ * Position PDAs are indexed per user and market; the next index is the
+ * UserMarket account's positionCount (0 if it does not exist yet)
+ */
+export function getUserPositionPDA(
+  programId: PublicKey,
+  userPubkey: PublicKey,
+  marketPubkey: PublicKey,
+  positionIndex: BN
 ): [PublicKey, number] {
   return PublicKey.findProgramAddressSync(
     [
       Buffer.from('position'),
       userPubkey.toBuffer(),
       marketPubkey.toBuffer(),
+      positionIndex.toArrayLike(Buffer, 'le', 8),
     ],
     programId
   );
 }
 
-export function getVaultPDA(programId: PublicKey): [PublicKey, number] {
+/**
+ * Lamport vault escrowing a market's SOL stakes
+ */
+export function getVaultPDA(
+  programId: PublicKey,
+  marketPubkey: PublicKey
+): [PublicKey, number] {
   return PublicKey.findProgramAddressSync(
-    [Buffer.from('vault')],
+    [Buffer.from('vault'), marketPubkey.toBuffer()],
     programId
   );
 }
@@ -406,29 +443,85 @@ export function getUserProfilePDA(
   );
 }
 
+/**
+ * Domain-separation tag for bet commitments (must match COMMITMENT_DOMAIN on-chain)
+ */
+export const COMMITMENT_DOMAIN = 'darkbet:commitment:v1';
+
+/**
+ * Outcome indices of the two sides of a binary market
+ */
+export const OUTCOME_LONG = 0;
+export const OUTCOME_SHORT = 1;
+
 /**
  * Hash commitment for commit-reveal scheme
+ * SHA-256(domain || programId || market || user || outcome (u8) || stake (u64 LE) || salt)
  */
 export async function hashCommitment(
-  direction: 'LONG' | 'SHORT',
-  nonce: string,
-  timestamp: number
+  programId: PublicKey,
+  market: PublicKey,
+  user: PublicKey,
+  outcome: number,
+  stakeAmount: BN,
+  salt: Uint8Array
 ): Promise<Uint8Array> {
+  if (salt.length !== 32) {
+    throw new Error('Commitment salt must be 32 bytes');
+  }
+  if (!Number.isInteger(outcome) || outcome < 0 || outcome > 255) {
+    throw new Error('Outcome must be a u8 index');
+  }
+
   // Use crypto.subtle for hashing in browser
-  const directionByte = direction === 'LONG' ? 1 : 2;
   const data = new Uint8Array([
-    directionByte,
-    ...new TextEncoder().encode(nonce),
-    ...new Uint8Array(new BigInt64Array([BigInt(timestamp)]).buffer),
+    ...new TextEncoder().encode(COMMITMENT_DOMAIN),
+    ...programId.toBytes(),
+    ...market.toBytes(),
+    ...user.toBytes(),
+    outcome,
+    ...stakeAmount.toArray('le', 8),
+    ...salt,
   ]);
-  
+
   const hashBuffer = await crypto.subtle.digest('SHA-256', data);
   return new Uint8Array(hashBuffer);
 }
 
 /**
- * Generate random nonce for commit-reveal
+ * Domain-separation tag for relayed reveals (must match REVEAL_DOMAIN on-chain)
+ */
+export const REVEAL_DOMAIN = 'darkbet:reveal:v1';
+
+/**
+ * Message a position owner signs (ed25519) so a relayer can call reveal_bet_for
+ * domain || programId || position || outcome (u8) || salt
+ */
+export function revealMessage(
+  programId: PublicKey,
+  position: PublicKey,
+  outcome: number,
+  salt: Uint8Array
+): Uint8Array {
+  if (salt.length !== 32) {
+    throw new Error('Commitment salt must be 32 bytes');
+  }
+  if (!Number.isInteger(outcome) || outcome < 0 || outcome > 255) {
+    throw new Error('Outcome must be a u8 index');
+  }
+
+  return new Uint8Array([
+    ...new TextEncoder().encode(REVEAL_DOMAIN),
+    ...programId.toBytes(),
+    ...position.toBytes(),
+    outcome,
+    ...salt,
+  ]);
+}
+
+/**
+ * Generate random 32-byte salt for commit-reveal
  */
-export function generateNonce(): string {
-  return crypto.randomUUID();
+export function generateSalt(): Uint8Array {
+  return crypto.getRandomValues(new Uint8Array(32));
 }
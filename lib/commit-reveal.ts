@@ -1,4 +1,7 @@
 import { ethers } from 'ethers';
+import { PublicKey } from '@solana/web3.js';
+import { BN } from '@coral-xyz/anchor';
+import { hashCommitment, generateSalt } from './blockchain-utils';
 
 /**
  * Commit-Reveal darkpool betting utilities
@@ -50,6 +53,58 @@ export function verifyCommit(
   return computedHash.toLowerCase() === commitHash.toLowerCase();
 }
 
+/**
+ * Generate a commitment for the Solana prediction market program.
+ * The hash binds the program, market, user, outcome and stake, so it
+ * cannot be replayed elsewhere and does not depend on the commit timestamp.
+ */
+export async function generateSolanaCommit(
+  programId: PublicKey,
+  market: PublicKey,
+  user: PublicKey,
+  outcome: number,
+  stakeAmount: BN
+): Promise<{ commitmentHash: Uint8Array; salt: Uint8Array }> {
+  const salt = generateSalt();
+  const commitmentHash = await hashCommitment(
+    programId,
+    market,
+    user,
+    outcome,
+    stakeAmount,
+    salt
+  );
+
+  return { commitmentHash, salt };
+}
+
+/**
+ * Verify a Solana commitment matches the reveal
+ */
+export async function verifySolanaCommit(
+  commitmentHash: Uint8Array,
+  programId: PublicKey,
+  market: PublicKey,
+  user: PublicKey,
+  outcome: number,
+  stakeAmount: BN,
+  salt: Uint8Array
+): Promise<boolean> {
+  const computedHash = await hashCommitment(
+    programId,
+    market,
+    user,
+    outcome,
+    stakeAmount,
+    salt
+  );
+
+  return (
+    computedHash.length === commitmentHash.length &&
+    computedHash.every((byte, i) => byte === commitmentHash[i])
+  );
+}
+
 /**
  * Store commit secret in localStorage (encrypted in production)
  */
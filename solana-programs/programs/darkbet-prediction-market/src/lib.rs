@@ -1,177 +1,545 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::ed25519_program;
+use anchor_lang::solana_program::sysvar::instructions as sysvar_instructions;
+use anchor_lang::system_program;
+use anchor_spl::token::{self, Mint, Token, TokenAccount};
 
 // This is a placeholder program ID - will be updated after deployment
 declare_id!("11111111111111111111111111111111");
 
+/// Denominator for all basis-point values
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/// Upper bound on protocol fees (10%)
+pub const MAX_FEE_BPS: u16 = 1_000;
+
+/// Decimals of native SOL (lamports)
+pub const SOL_DECIMALS: u8 = 9;
+
+/// Maximum number of outcomes in a categorical market
+pub const MAX_OUTCOMES: usize = 8;
+
+/// High bit marking market IDs derived for market series rounds
+pub const SERIES_MARKET_ID_FLAG: u64 = 1 << 63;
+
+/// Domain-separation tag prefixed to every relayed reveal authorization
+pub const REVEAL_DOMAIN: &[u8] = b"darkbet:reveal:v1";
+
+/// Domain-separation tag prefixed to every bet commitment
+pub const COMMITMENT_DOMAIN: &[u8] = b"darkbet:commitment:v1";
+
 #[program]
 pub mod darkbet_prediction_market {
     use super::*;
 
-    /// Initialize a new prediction market
+    /// Create the global program config and market ID counter; the signer becomes the admin
+    pub fn initialize_config(ctx: Context<InitializeConfig>, params: ConfigParams) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let counter = &mut ctx.accounts.market_counter;
+
+        config.admin = ctx.accounts.admin.key();
+        config.bump = ctx.bumps.config;
+        config.apply(&params)?;
+
+        counter.next_market_id = 1;
+        counter.bump = ctx.bumps.market_counter;
+
+        emit!(ConfigUpdated {
+            admin: config.admin,
+            params,
+        });
+
+        msg!("Program config initialized with {} bps fee", config.fee_bps);
+        Ok(())
+    }
+
+    /// Replace the program config parameters (admin only).
+    /// Markets snapshot these values at creation, so changes apply to new markets.
+    pub fn update_config(ctx: Context<UpdateConfig>, params: ConfigParams) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+
+        config.apply(&params)?;
+
+        emit!(ConfigUpdated {
+            admin: config.admin,
+            params,
+        });
+
+        msg!("Program config updated");
+        Ok(())
+    }
+
+    /// Create a new prediction market under the next sequential market ID
+    pub fn create_market(ctx: Context<CreateMarket>, params: MarketParams) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let counter = &mut ctx.accounts.market_counter;
+        let clock = Clock::get()?;
+
+        let market_id = counter.next_market_id;
+        counter.next_market_id = market_id.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+
+        market.initialize(
+            market_id,
+            ctx.accounts.authority.key(),
+            params,
+            &ctx.accounts.config,
+            clock.unix_timestamp,
+        )?;
+        market.bump = ctx.bumps.market;
+        market.vault_bump = ctx.bumps.vault;
+        market.rent_payer = ctx.accounts.authority.key();
+        market.set_custody(
+            ctx.accounts.stake_mint.as_ref(),
+            &ctx.accounts.token_vault,
+            &ctx.accounts.token_program,
+            ctx.bumps.token_vault,
+            &ctx.accounts.config,
+        )?;
+
+        fund_vault(
+            &ctx.accounts.authority,
+            &ctx.accounts.vault,
+            &ctx.accounts.system_program,
+            market.keeper_tip_pool,
+        )?;
+
+        emit!(MarketCreated {
+            market_id,
+            authority: market.authority,
+            asset_type: market.asset_type,
+            resolution_time: market.resolution_time,
+            threshold_price: market.threshold_price,
+            market_type: market.market_type,
+        });
+
+        msg!("Market {} created", market_id);
+        Ok(())
+    }
+
+    /// Initialize a new prediction market under an explicit market ID (admin only).
+    /// The counter skips past the ID so `create_market` never collides with it.
     pub fn initialize_market(
         ctx: Context<InitializeMarket>,
         market_id: u64,
-        asset_type: AssetType,
-        resolution_time: i64,
-        pyth_feed_account: Pubkey,
-        threshold_price: i64,
+        params: MarketParams,
     ) -> Result<()> {
         let market = &mut ctx.accounts.market;
+        let counter = &mut ctx.accounts.market_counter;
         let clock = Clock::get()?;
 
-        // Validate resolution time is in the future
+        // Derived IDs belong to market series rounds
         require!(
-            resolution_time > clock.unix_timestamp,
-            ErrorCode::InvalidResolutionTime
+            market_id & SERIES_MARKET_ID_FLAG == 0,
+            ErrorCode::ReservedMarketId
         );
 
-        // Initialize market state
-        market.authority = ctx.accounts.authority.key();
-        market.market_id = market_id;
-        market.asset_type = asset_type;
-        market.resolution_time = resolution_time;
-        market.pyth_feed_account = pyth_feed_account;
-        market.threshold_price = threshold_price;
-        market.total_long_stake = 0;
-        market.total_short_stake = 0;
-        market.status = MarketStatus::Open;
-        market.settlement_price = None;
-        market.created_at = clock.unix_timestamp;
+        if market_id >= counter.next_market_id {
+            counter.next_market_id = market_id + 1;
+        }
+
+        market.initialize(
+            market_id,
+            ctx.accounts.authority.key(),
+            params,
+            &ctx.accounts.config,
+            clock.unix_timestamp,
+        )?;
         market.bump = ctx.bumps.market;
+        market.vault_bump = ctx.bumps.vault;
+        market.rent_payer = ctx.accounts.authority.key();
+        market.set_custody(
+            ctx.accounts.stake_mint.as_ref(),
+            &ctx.accounts.token_vault,
+            &ctx.accounts.token_program,
+            ctx.bumps.token_vault,
+            &ctx.accounts.config,
+        )?;
+
+        fund_vault(
+            &ctx.accounts.authority,
+            &ctx.accounts.vault,
+            &ctx.accounts.system_program,
+            market.keeper_tip_pool,
+        )?;
 
         emit!(MarketCreated {
             market_id,
             authority: market.authority,
-            asset_type,
-            resolution_time,
-            threshold_price,
+            asset_type: market.asset_type,
+            resolution_time: market.resolution_time,
+            threshold_price: market.threshold_price,
+            market_type: market.market_type,
         });
 
         msg!("Market {} initialized successfully", market_id);
         Ok(())
     }
 
-    /// Commit a bet (commit phase of commit-reveal)
-    pub fn commit_bet(
-        ctx: Context<CommitBet>,
-        stake_amount: u64,
-        commitment_hash: [u8; 32],
+    /// Create a recurring market series; its rounds are created with `create_series_round`
+    pub fn create_series(
+        ctx: Context<CreateSeries>,
+        series_id: u64,
+        template: SeriesTemplate,
+        first_resolution_time: i64,
     ) -> Result<()> {
-        let market = &ctx.accounts.market;
-        let position = &mut ctx.accounts.position;
+        let series = &mut ctx.accounts.series;
         let clock = Clock::get()?;
 
-        // Validate market is open
+        // Every round needs a betting window before it locks
         require!(
-            market.status == MarketStatus::Open,
-            ErrorCode::MarketNotOpen
+            template.interval > ctx.accounts.config.lock_buffer,
+            ErrorCode::InvalidSeriesTemplate
         );
 
-        // Validate market hasn't reached lock time
-        require!(
-            clock.unix_timestamp < market.resolution_time - 300, // 5 minutes before resolution
-            ErrorCode::MarketLocked
-        );
+        // Validate the template by checking the first round's parameters
+        template
+            .market_params(first_resolution_time, template.threshold_rule.initial_price())
+            .validate(clock.unix_timestamp, ctx.accounts.config.lock_buffer)?;
+
+        series.authority = ctx.accounts.authority.key();
+        series.series_id = series_id;
+        series.template = template;
+        series.next_round = 0;
+        series.latest_market = None;
+        series.latest_resolution_time = first_resolution_time - template.interval;
+        series.bump = ctx.bumps.series;
+
+        emit!(SeriesCreated {
+            series: series.key(),
+            series_id,
+            authority: series.authority,
+            template,
+            first_resolution_time,
+        });
+
+        msg!("Market series {} created", series_id);
+        Ok(())
+    }
+
+    /// Create the next round of a series (permissionless). The previous round must have
+    /// been resolved (provisionally or finally) or cancelled. The round resolves one interval
+    /// after the previous one, skipping ahead whole intervals if that time has already passed.
+    pub fn create_series_round(ctx: Context<CreateSeriesRound>) -> Result<()> {
+        let series = &mut ctx.accounts.series;
+        let market = &mut ctx.accounts.market;
+        let clock = Clock::get()?;
+
+        // The previous round, if any, must be passed in and settled
+        let previous_settlement = match series.latest_market {
+            None => None,
+            Some(latest_market) => {
+                let previous = ctx
+                    .accounts
+                    .previous_market
+                    .as_ref()
+                    .ok_or(ErrorCode::InvalidPreviousRound)?;
+                require_keys_eq!(previous.key(), latest_market, ErrorCode::InvalidPreviousRound);
+                require!(
+                    matches!(
+                        previous.status,
+                        MarketStatus::Provisional | MarketStatus::Resolved | MarketStatus::Cancelled
+                    ),
+                    ErrorCode::PreviousRoundNotSettled
+                );
+                // Cancelled rounds carry their threshold forward
+                Some(previous.settlement_price.unwrap_or(previous.threshold_price))
+            }
+        };
+
+        let threshold_price = match series.template.threshold_rule {
+            ThresholdRule::Fixed { price } => price,
+            ThresholdRule::LastSettlement { initial_price } => {
+                previous_settlement.unwrap_or(initial_price)
+            }
+        };
+        let resolution_time = next_round_time(
+            series.latest_resolution_time,
+            series.template.interval,
+            ctx.accounts.config.lock_buffer,
+            clock.unix_timestamp,
+        )?;
+
+        let round = series.next_round;
+        let market_id = series_market_id(&series.key(), round);
+        market.initialize(
+            market_id,
+            series.authority,
+            series.template.market_params(resolution_time, threshold_price),
+            &ctx.accounts.config,
+            clock.unix_timestamp,
+        )?;
+        market.bump = ctx.bumps.market;
+        market.vault_bump = ctx.bumps.vault;
+        market.rent_payer = ctx.accounts.creator.key();
+        market.series = Some(series.key());
 
-        // Validate stake amount
+        // Rounds stake in the series' token
         require!(
-            stake_amount >= 10_000_000, // Minimum 0.01 SOL
-            ErrorCode::StakeTooLow
+            ctx.accounts.stake_mint.as_ref().map(|mint| mint.key()) == series.template.stake_mint,
+            ErrorCode::InvalidStakeMint
         );
-        require!(
-            stake_amount <= 100_000_000_000, // Maximum 100 SOL
-            ErrorCode::StakeTooHigh
+        market.set_custody(
+            ctx.accounts.stake_mint.as_ref(),
+            &ctx.accounts.token_vault,
+            &ctx.accounts.token_program,
+            ctx.bumps.token_vault,
+            &ctx.accounts.config,
+        )?;
+
+        fund_vault(
+            &ctx.accounts.creator,
+            &ctx.accounts.vault,
+            &ctx.accounts.system_program,
+            market.keeper_tip_pool,
+        )?;
+
+        series.next_round = round.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        series.latest_market = Some(market.key());
+        series.latest_resolution_time = resolution_time;
+
+        emit!(MarketCreated {
+            market_id,
+            authority: market.authority,
+            asset_type: market.asset_type,
+            resolution_time,
+            threshold_price,
+            market_type: market.market_type,
+        });
+
+        emit!(SeriesRoundCreated {
+            series: series.key(),
+            round,
+            market: market.key(),
+            market_id,
+            created_by: ctx.accounts.creator.key(),
+        });
+
+        msg!(
+            "Series {} round {} created as market {}",
+            series.series_id,
+            round,
+            market_id
         );
+        Ok(())
+    }
+
+    /// Commit a bet (commit phase of commit-reveal)
+    pub fn commit_bet(
+        ctx: Context<CommitBet>,
+        stake_amount: u64,
+        commitment_hash: [u8; 32],
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let user_market = &mut ctx.accounts.user_market;
+        let position = &mut ctx.accounts.position;
+        let clock = Clock::get()?;
+
+        // Validate timing and stake bounds, and count the new position
+        market.commit(stake_amount, clock.unix_timestamp)?;
 
         // Initialize position
         position.user = ctx.accounts.user.key();
         position.market = market.key();
         position.stake_amount = stake_amount;
         position.commitment_hash = commitment_hash;
-        position.direction = None;
+        position.outcome = None;
         position.revealed = false;
         position.claimed = false;
         position.committed_at = clock.unix_timestamp;
         position.bump = ctx.bumps.position;
+        position.index = user_market.position_count;
 
-        // Transfer SOL from user to vault (will be implemented with vault program)
-        // For now, we'll just track the stake amount
+        // First commit on this market creates the user's position counter
+        if user_market.position_count == 0 {
+            user_market.user = position.user;
+            user_market.market = position.market;
+            user_market.bump = ctx.bumps.user_market;
+        }
+        user_market.position_count = user_market
+            .position_count
+            .checked_add(1)
+            .ok_or(ErrorCode::MathOverflow)?;
+        user_market.open_positions = user_market
+            .open_positions
+            .checked_add(1)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        // Transfer the stake from user to the market vault
+        MarketCustody::new(
+            market,
+            &ctx.accounts.vault,
+            &ctx.accounts.token_vault,
+            &ctx.accounts.token_program,
+            &ctx.accounts.system_program,
+        )
+        .deposit(
+            &ctx.accounts.user,
+            ctx.accounts.user_token_account.as_ref(),
+            stake_amount,
+        )?;
 
         emit!(BetCommitted {
             user: position.user,
             market: position.market,
+            position_index: position.index,
             stake_amount,
             commitment_hash,
         });
 
-        msg!("Bet committed by {} for {} lamports", position.user, stake_amount);
+        msg!(
+            "Bet {} committed by {} with stake {}",
+            position.index,
+            position.user,
+            stake_amount
+        );
+        Ok(())
+    }
+
+    /// Add stake to an unrevealed commitment before the market locks.
+    /// The new commitment hash must bind the new total stake.
+    pub fn top_up_commitment(
+        ctx: Context<TopUpCommitment>,
+        additional_stake: u64,
+        commitment_hash: [u8; 32],
+    ) -> Result<()> {
+        let market = &ctx.accounts.market;
+        let position = &mut ctx.accounts.position;
+        let clock = Clock::get()?;
+
+        let new_stake =
+            market.top_up(position.stake_amount, additional_stake, clock.unix_timestamp)?;
+
+        position.stake_amount = new_stake;
+        position.commitment_hash = commitment_hash;
+
+        MarketCustody::new(
+            market,
+            &ctx.accounts.vault,
+            &ctx.accounts.token_vault,
+            &ctx.accounts.token_program,
+            &ctx.accounts.system_program,
+        )
+        .deposit(
+            &ctx.accounts.user,
+            ctx.accounts.user_token_account.as_ref(),
+            additional_stake,
+        )?;
+
+        emit!(CommitmentToppedUp {
+            user: position.user,
+            market: position.market,
+            position_index: position.index,
+            additional_stake,
+            stake_amount: new_stake,
+            commitment_hash,
+        });
+
+        msg!(
+            "Bet {} topped up by {} to {}",
+            position.index,
+            additional_stake,
+            new_stake
+        );
+        Ok(())
+    }
+
+    /// Withdraw an unrevealed commitment before the market locks.
+    /// Refunds the stake minus the early-exit fee and closes the position, returning its rent.
+    pub fn withdraw_commitment(ctx: Context<WithdrawCommitment>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let position = &ctx.accounts.position;
+        let clock = Clock::get()?;
+
+        let (refund, fee) =
+            market.withdraw_commitment(position.stake_amount, clock.unix_timestamp)?;
+        ctx.accounts.user_market.open_positions -= 1;
+
+        MarketCustody::new(
+            market,
+            &ctx.accounts.vault,
+            &ctx.accounts.token_vault,
+            &ctx.accounts.token_program,
+            &ctx.accounts.system_program,
+        )
+        .withdraw(
+            &ctx.accounts.user.to_account_info(),
+            ctx.accounts.user_token_account.as_ref(),
+            refund,
+        )?;
+
+        emit!(CommitmentWithdrawn {
+            user: position.user,
+            market: position.market,
+            position_index: position.index,
+            stake_amount: position.stake_amount,
+            refunded: refund,
+            fee,
+        });
+
+        msg!(
+            "Bet {} withdrawn by {}: {} refunded, {} fee",
+            position.index,
+            position.user,
+            refund,
+            fee
+        );
         Ok(())
     }
 
     /// Reveal a bet (reveal phase of commit-reveal)
     pub fn reveal_bet(
         ctx: Context<RevealBet>,
-        direction: Direction,
-        nonce: String,
+        outcome: u8,
+        salt: [u8; 32],
     ) -> Result<()> {
         let market = &mut ctx.accounts.market;
         let position = &mut ctx.accounts.position;
-        let clock = Clock::get()?;
 
-        // Validate market is locked
-        require!(
-            market.status == MarketStatus::Locked,
-            ErrorCode::MarketNotLocked
-        );
+        reveal_position(market, position, outcome, &salt)?;
 
-        // Validate reveal window (5 minutes after lock)
-        let lock_time = market.resolution_time - 300;
-        require!(
-            clock.unix_timestamp >= lock_time && clock.unix_timestamp < market.resolution_time,
-            ErrorCode::RevealWindowClosed
-        );
+        emit!(BetRevealed {
+            user: position.user,
+            market: position.market,
+            outcome,
+            stake_amount: position.stake_amount,
+            revealed_by: position.user,
+        });
 
-        // Validate not already revealed
-        require!(
-            !position.revealed,
-            ErrorCode::AlreadyRevealed
-        );
+        msg!("Bet revealed: outcome {}", outcome);
+        Ok(())
+    }
+
+    /// Reveal a bet on the owner's behalf. The owner must sign the reveal message (see
+    /// `reveal_message`) in an ed25519 program instruction earlier in the transaction, so a
+    /// relayer can reveal while the owner is offline. One ed25519 instruction may carry many
+    /// signatures, so several reveals can be batched into one transaction.
+    pub fn reveal_bet_for(
+        ctx: Context<RevealBetFor>,
+        outcome: u8,
+        salt: [u8; 32],
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let position = &mut ctx.accounts.position;
 
-        // Verify commitment hash
-        let computed_hash = hash_commitment(&direction, &nonce, position.committed_at);
+        // Verify the owner authorized exactly this reveal
+        let expected = reveal_message(&position.key(), outcome, &salt);
+        let signed = ed25519_signed_messages(&ctx.accounts.instructions, &position.user)?;
         require!(
-            computed_hash == position.commitment_hash,
-            ErrorCode::InvalidCommitment
+            signed.contains(&expected),
+            ErrorCode::MissingRevealSignature
         );
 
-        // Update position
-        position.direction = Some(direction.clone());
-        position.revealed = true;
-
-        // Update market stakes
-        match direction {
-            Direction::Long => {
-                market.total_long_stake = market
-                    .total_long_stake
-                    .checked_add(position.stake_amount)
-                    .ok_or(ErrorCode::MathOverflow)?;
-            }
-            Direction::Short => {
-                market.total_short_stake = market
-                    .total_short_stake
-                    .checked_add(position.stake_amount)
-                    .ok_or(ErrorCode::MathOverflow)?;
-            }
-        }
+        reveal_position(market, position, outcome, &salt)?;
 
         emit!(BetRevealed {
             user: position.user,
             market: position.market,
-            direction,
+            outcome,
             stake_amount: position.stake_amount,
+            revealed_by: ctx.accounts.relayer.key(),
         });
 
-        msg!("Bet revealed: {:?}", direction);
+        msg!("Bet of {} revealed by relayer: outcome {}", position.user, outcome);
         Ok(())
     }
 
@@ -188,12 +556,19 @@ pub mod darkbet_prediction_market {
 
         // Validate lock time has been reached
         require!(
-            clock.unix_timestamp >= market.resolution_time - 300,
+            clock.unix_timestamp >= market.lock_time,
             ErrorCode::LockTimeNotReached
         );
 
         market.status = MarketStatus::Locked;
 
+        pay_keeper_tip(
+            market,
+            &ctx.accounts.vault,
+            &ctx.accounts.caller,
+            &ctx.accounts.system_program,
+        )?;
+
         emit!(MarketLocked {
             market_id: market.market_id,
             lock_time: clock.unix_timestamp,
@@ -202,229 +577,4553 @@ pub mod darkbet_prediction_market {
         msg!("Market {} locked", market.market_id);
         Ok(())
     }
-}
 
-// ============================================================================
-// Account Structures
-// ============================================================================
+    /// Resolve the market against its Pyth price feed
+    pub fn resolve_market(ctx: Context<ResolveMarket>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let clock = Clock::get()?;
 
-#[derive(Accounts)]
-#[instruction(market_id: u64)]
-pub struct InitializeMarket<'info> {
-    #[account(
-        init,
-        payer = authority,
-        space = 8 + Market::INIT_SPACE,
-        seeds = [b"market", market_id.to_le_bytes().as_ref()],
-        bump
-    )]
-    pub market: Account<'info, Market>,
+        // Validate market is locked
+        require!(
+            market.status == MarketStatus::Locked,
+            ErrorCode::MarketNotLocked
+        );
 
-    #[account(mut)]
-    pub authority: Signer<'info>,
+        // Validate resolution time has been reached
+        require!(
+            clock.unix_timestamp >= market.resolution_time,
+            ErrorCode::ResolutionTimeNotReached
+        );
 
-    pub system_program: Program<'info, System>,
-}
+        // Read the price from the oracle source chosen at market creation
+        let price = market.oracle_source.read_price(
+            ctx.accounts.oracle_account.as_ref().map(|account| account.as_ref()),
+            ctx.accounts.instructions.as_ref().map(|account| account.as_ref()),
+            market.resolution_time,
+        )?;
+        market.validate_settlement_price(&price)?;
 
-#[derive(Accounts)]
-pub struct CommitBet<'info> {
-    #[account(
-        init,
-        payer = user,
-        space = 8 + UserPosition::INIT_SPACE,
-        seeds = [b"position", user.key().as_ref(), market.key().as_ref()],
-        bump
-    )]
-    pub position: Account<'info, UserPosition>,
+        // Only price-settled markets read the oracle
+        let winning_outcome = market.outcome_for_price(price.price)?;
 
-    #[account(mut, seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
-    pub market: Account<'info, Market>,
+        market.settlement_price = Some(price.price);
+        market.winning_outcome = winning_outcome;
+        market.status = MarketStatus::Provisional;
+        market.dispute_deadline = clock
+            .unix_timestamp
+            .checked_add(market.dispute_window)
+            .ok_or(ErrorCode::MathOverflow)?;
 
-    #[account(mut)]
-    pub user: Signer<'info>,
+        pay_keeper_tip(
+            market,
+            &ctx.accounts.vault,
+            &ctx.accounts.caller,
+            &ctx.accounts.system_program,
+        )?;
 
-    pub system_program: Program<'info, System>,
-}
+        emit!(MarketResolved {
+            market_id: market.market_id,
+            settlement_price: Some(price.price),
+            threshold_price: market.threshold_price,
+            winning_outcome,
+            resolved_at: clock.unix_timestamp,
+            dispute_deadline: market.dispute_deadline,
+            manual: false,
+        });
 
-#[derive(Accounts)]
-pub struct RevealBet<'info> {
-    #[account(
-        mut,
-        seeds = [b"position", user.key().as_ref(), market.key().as_ref()],
-        bump = position.bump,
-        has_one = user,
-        has_one = market
-    )]
-    pub position: Account<'info, UserPosition>,
+        msg!(
+            "Market {} provisionally resolved at price {}: outcome {:?} wins",
+            market.market_id,
+            price.price,
+            winning_outcome
+        );
+        Ok(())
+    }
 
-    #[account(mut, seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
-    pub market: Account<'info, Market>,
+    /// Resolve a categorical market by declaring the winning outcome (config resolver or arbiter only)
+    pub fn resolve_categorical_market(
+        ctx: Context<ResolveCategoricalMarket>,
+        winning_outcome: u8,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let clock = Clock::get()?;
 
-    pub user: Signer<'info>,
-}
+        // Validate market is locked
+        require!(
+            market.status == MarketStatus::Locked,
+            ErrorCode::MarketNotLocked
+        );
 
-#[derive(Accounts)]
-pub struct LockMarket<'info> {
-    #[account(mut, seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
-    pub market: Account<'info, Market>,
+        // Validate resolution time has been reached
+        require!(
+            clock.unix_timestamp >= market.resolution_time,
+            ErrorCode::ResolutionTimeNotReached
+        );
 
-    /// Anyone can call lock_market when the time is reached
-    pub caller: Signer<'info>,
-}
+        require!(
+            matches!(market.market_type, MarketType::Categorical { .. }),
+            ErrorCode::MarketTypeMismatch
+        );
+        require!(
+            winning_outcome < market.outcome_count,
+            ErrorCode::InvalidOutcome
+        );
 
-// ============================================================================
-// Account State
-// ============================================================================
+        market.winning_outcome = Some(winning_outcome);
+        market.status = MarketStatus::Provisional;
+        market.dispute_deadline = clock
+            .unix_timestamp
+            .checked_add(market.dispute_window)
+            .ok_or(ErrorCode::MathOverflow)?;
 
-#[account]
-#[derive(InitSpace)]
-pub struct Market {
-    pub authority: Pubkey,           // 32
-    pub market_id: u64,              // 8
+        emit!(MarketResolved {
+            market_id: market.market_id,
+            settlement_price: None,
+            threshold_price: market.threshold_price,
+            winning_outcome: Some(winning_outcome),
+            resolved_at: clock.unix_timestamp,
+            dispute_deadline: market.dispute_deadline,
+            manual: false,
+        });
+
+        msg!(
+            "Market {} provisionally resolved: outcome {} wins",
+            market.market_id,
+            winning_outcome
+        );
+        Ok(())
+    }
+
+    /// Provisionally resolve a price-settled market at a resolver-supplied price when the
+    /// oracle has not delivered (designated resolver only, after the oracle timeout).
+    /// The result is marked as manual and can be challenged like any other resolution.
+    pub fn manual_resolve(ctx: Context<ManualResolve>, settlement_price: i64) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let clock = Clock::get()?;
+
+        // Validate market is locked
+        require!(
+            market.status == MarketStatus::Locked,
+            ErrorCode::MarketNotLocked
+        );
+
+        // Validate the oracle has had its chance to settle the market
+        let oracle_deadline = market
+            .resolution_time
+            .checked_add(market.oracle_timeout)
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(
+            clock.unix_timestamp >= oracle_deadline,
+            ErrorCode::OracleTimeoutNotReached
+        );
+
+        let winning_outcome = market.outcome_for_price(settlement_price)?;
+
+        market.settlement_price = Some(settlement_price);
+        market.winning_outcome = winning_outcome;
+        market.manually_resolved = true;
+        market.status = MarketStatus::Provisional;
+        market.dispute_deadline = clock
+            .unix_timestamp
+            .checked_add(market.dispute_window)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        emit!(MarketResolved {
+            market_id: market.market_id,
+            settlement_price: Some(settlement_price),
+            threshold_price: market.threshold_price,
+            winning_outcome,
+            resolved_at: clock.unix_timestamp,
+            dispute_deadline: market.dispute_deadline,
+            manual: true,
+        });
+
+        msg!(
+            "Market {} manually resolved at price {}: outcome {:?} wins",
+            market.market_id,
+            settlement_price,
+            winning_outcome
+        );
+        Ok(())
+    }
+
+    /// Challenge a provisional resolution by posting the market's dispute bond (in SOL)
+    pub fn challenge_resolution(ctx: Context<ChallengeResolution>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let dispute = &mut ctx.accounts.dispute;
+        let clock = Clock::get()?;
+
+        market.challenge(clock.unix_timestamp)?;
+
+        // Bonds are always posted in SOL, held in the market vault until the dispute is settled
+        MarketCustody::new(
+            market,
+            &ctx.accounts.vault,
+            &None,
+            &None,
+            &ctx.accounts.system_program,
+        )
+        .deposit_lamports(&ctx.accounts.challenger, market.dispute_bond)?;
+
+        dispute.market = market.key();
+        dispute.challenger = ctx.accounts.challenger.key();
+        dispute.bond = market.dispute_bond;
+        dispute.challenged_at = clock.unix_timestamp;
+        dispute.bump = ctx.bumps.dispute;
+
+        emit!(ResolutionChallenged {
+            market: dispute.market,
+            challenger: dispute.challenger,
+            bond: dispute.bond,
+            challenged_at: clock.unix_timestamp,
+        });
+
+        msg!(
+            "Resolution of market {} challenged by {}",
+            market.market_id,
+            dispute.challenger
+        );
+        Ok(())
+    }
+
+    /// Finalize an unchallenged provisional resolution once the dispute window closes.
+    /// A challenged resolution the arbiter has not ruled on by the arbitration deadline is
+    /// finalized as it stands, and its challengers get their bonds back.
+    pub fn finalize_resolution(ctx: Context<FinalizeResolution>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let clock = Clock::get()?;
+
+        market.finalize(clock.unix_timestamp)?;
+
+        pay_keeper_tip(
+            market,
+            &ctx.accounts.vault,
+            &ctx.accounts.caller,
+            &ctx.accounts.system_program,
+        )?;
+
+        emit!(ResolutionFinalized {
+            market_id: market.market_id,
+            settlement_price: market.settlement_price,
+            winning_outcome: market.winning_outcome,
+            overridden: false,
+            finalized_at: clock.unix_timestamp,
+        });
+
+        msg!("Market {} resolution finalized", market.market_id);
+        Ok(())
+    }
+
+    /// Rule on a challenged resolution, confirming or overriding it (arbiter only).
+    /// The market becomes final and its disputes can then be settled.
+    pub fn arbitrate_resolution(
+        ctx: Context<ArbitrateResolution>,
+        ruling: ArbiterRuling,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let clock = Clock::get()?;
+
+        market.arbitrate(ruling, clock.unix_timestamp)?;
+
+        emit!(ResolutionFinalized {
+            market_id: market.market_id,
+            settlement_price: market.settlement_price,
+            winning_outcome: market.winning_outcome,
+            overridden: market.resolution_overridden,
+            finalized_at: clock.unix_timestamp,
+        });
+
+        msg!(
+            "Market {} resolution {} by arbiter",
+            market.market_id,
+            if market.resolution_overridden { "overridden" } else { "confirmed" }
+        );
+        Ok(())
+    }
+
+    /// Settle a dispute on a final market: the bond is returned if the arbiter overrode
+    /// the resolution or never ruled, otherwise it is slashed to the treasury. The dispute
+    /// account's rent goes back to the challenger either way.
+    pub fn settle_dispute(ctx: Context<SettleDispute>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let dispute = &ctx.accounts.dispute;
+
+        let returned = market.settle_dispute()?;
+        let recipient = if returned {
+            ctx.accounts.challenger.to_account_info()
+        } else {
+            ctx.accounts.treasury.to_account_info()
+        };
+
+        MarketCustody::new(
+            market,
+            &ctx.accounts.vault,
+            &None,
+            &None,
+            &ctx.accounts.system_program,
+        )
+        .withdraw_lamports(&recipient, dispute.bond)?;
+
+        emit!(DisputeSettled {
+            market: dispute.market,
+            challenger: dispute.challenger,
+            bond: dispute.bond,
+            returned,
+        });
+
+        msg!(
+            "Dispute by {} settled: bond {}",
+            dispute.challenger,
+            if returned { "returned" } else { "slashed" }
+        );
+        Ok(())
+    }
+
+    /// Claim a winning position's pro-rata share of the losing pool plus its stake
+    pub fn claim_winnings(ctx: Context<ClaimWinnings>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let position = &mut ctx.accounts.position;
+
+        // Validate position is revealed and unclaimed
+        require!(!position.claimed, ErrorCode::AlreadyClaimed);
+        let outcome = position.outcome.ok_or(ErrorCode::NotRevealed)?;
+
+        let (payout, fee) = market.claim(outcome, position.stake_amount)?;
+        position.claimed = true;
+
+        MarketCustody::new(
+            market,
+            &ctx.accounts.vault,
+            &ctx.accounts.token_vault,
+            &ctx.accounts.token_program,
+            &ctx.accounts.system_program,
+        )
+        .withdraw(
+            &ctx.accounts.user.to_account_info(),
+            ctx.accounts.user_token_account.as_ref(),
+            payout,
+        )?;
+
+        emit!(WinningsClaimed {
+            user: position.user,
+            market: position.market,
+            stake_amount: position.stake_amount,
+            payout,
+            fee,
+        });
+
+        msg!("Winnings claimed by {}: {}", position.user, payout);
+        Ok(())
+    }
+
+    /// Cancel a market that has not been resolved.
+    /// The authority may cancel before the market locks; after that, anyone may cancel
+    /// once the oracle has failed to resolve within the grace period.
+    pub fn cancel_market(ctx: Context<CancelMarket>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let clock = Clock::get()?;
+
+        // Validate market has not been settled
+        require!(
+            market.status == MarketStatus::Open || market.status == MarketStatus::Locked,
+            ErrorCode::MarketNotCancellable
+        );
+
+        let before_lock = clock.unix_timestamp < market.lock_time;
+        let grace_period_end = market
+            .resolution_time
+            .checked_add(market.resolution_grace_period)
+            .ok_or(ErrorCode::MathOverflow)?;
+        let grace_period_elapsed = clock.unix_timestamp >= grace_period_end;
+        require!(
+            (before_lock && ctx.accounts.caller.key() == market.authority) || grace_period_elapsed,
+            ErrorCode::Unauthorized
+        );
+
+        market.status = MarketStatus::Cancelled;
+
+        emit!(MarketCancelled {
+            market_id: market.market_id,
+            cancelled_by: ctx.accounts.caller.key(),
+            oracle_timeout: grace_period_elapsed,
+            cancelled_at: clock.unix_timestamp,
+        });
+
+        msg!("Market {} cancelled", market.market_id);
+        Ok(())
+    }
+
+    /// Refund a position's full stake from a cancelled market, revealed or not
+    pub fn refund_position(ctx: Context<RefundPosition>) -> Result<()> {
+        let market = &ctx.accounts.market;
+        let position = &mut ctx.accounts.position;
+
+        // Validate not already refunded
+        require!(!position.claimed, ErrorCode::AlreadyClaimed);
+
+        let refund = market.refund(position.stake_amount)?;
+        position.claimed = true;
+
+        MarketCustody::new(
+            market,
+            &ctx.accounts.vault,
+            &ctx.accounts.token_vault,
+            &ctx.accounts.token_program,
+            &ctx.accounts.system_program,
+        )
+        .withdraw(
+            &ctx.accounts.user.to_account_info(),
+            ctx.accounts.user_token_account.as_ref(),
+            refund,
+        )?;
+
+        emit!(PositionRefunded {
+            user: position.user,
+            market: position.market,
+            stake_amount: refund,
+        });
+
+        msg!("Refunded {} to {}", refund, position.user);
+        Ok(())
+    }
+
+    /// Settle a position that was never revealed according to the market's unrevealed policy.
+    /// Anyone can call this once the market is resolved; claims open after every
+    /// unrevealed position has been swept, and that last sweep earns the keeper tip.
+    pub fn sweep_unrevealed(ctx: Context<SweepUnrevealed>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let position = &mut ctx.accounts.position;
+
+        // Validate position was never revealed and is not yet settled
+        require!(!position.revealed, ErrorCode::AlreadyRevealed);
+        require!(!position.claimed, ErrorCode::AlreadyClaimed);
+
+        let swept = market.sweep(position.stake_amount)?;
+        position.claimed = true;
+
+        let custody = MarketCustody::new(
+            market,
+            &ctx.accounts.vault,
+            &ctx.accounts.token_vault,
+            &ctx.accounts.token_program,
+            &ctx.accounts.system_program,
+        );
+        if swept.to_treasury > 0 {
+            let treasury_account = ctx
+                .accounts
+                .treasury
+                .as_ref()
+                .ok_or(ErrorCode::InvalidTreasury)?;
+            require_keys_eq!(
+                treasury_account.key(),
+                ctx.accounts.config.treasury,
+                ErrorCode::InvalidTreasury
+            );
+
+            custody.withdraw(
+                &treasury_account.to_account_info(),
+                ctx.accounts.treasury_token_account.as_ref(),
+                swept.to_treasury,
+            )?;
+        }
+        if swept.refunded > 0 {
+            custody.withdraw(
+                &ctx.accounts.user.to_account_info(),
+                ctx.accounts.user_token_account.as_ref(),
+                swept.refunded,
+            )?;
+        }
+
+        // One tip per market, for the sweep that opens claims: a tip per sweep would let a
+        // bettor farm the pool with cheap unrevealed positions
+        if market.unrevealed_positions == 0 {
+            pay_keeper_tip(
+                market,
+                &ctx.accounts.vault,
+                &ctx.accounts.caller,
+                &ctx.accounts.system_program,
+            )?;
+        }
+
+        emit!(UnrevealedSwept {
+            user: position.user,
+            market: position.market,
+            policy: market.unrevealed_policy,
+            stake_amount: position.stake_amount,
+            refunded: swept.refunded,
+            forfeited: swept.forfeited,
+        });
+
+        msg!(
+            "Unrevealed position of {} swept: {} refunded, {} forfeited",
+            position.user,
+            swept.refunded,
+            swept.forfeited
+        );
+        Ok(())
+    }
+
+    /// Send a market's accrued protocol fees to the treasury
+    pub fn withdraw_fees(ctx: Context<WithdrawFees>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let amount = market.fees_accrued;
+
+        require!(amount > 0, ErrorCode::NoFeesToWithdraw);
+
+        market.fees_accrued = 0;
+
+        MarketCustody::new(
+            market,
+            &ctx.accounts.vault,
+            &ctx.accounts.token_vault,
+            &ctx.accounts.token_program,
+            &ctx.accounts.system_program,
+        )
+        .withdraw(
+            &ctx.accounts.treasury.to_account_info(),
+            ctx.accounts.treasury_token_account.as_ref(),
+            amount,
+        )?;
+
+        emit!(FeesCollected {
+            market: market.key(),
+            treasury: ctx.accounts.treasury.key(),
+            amount,
+        });
+
+        msg!("Collected {} in fees from market {}", amount, market.market_id);
+        Ok(())
+    }
+
+    /// Close a settled position, returning its rent to the position owner (anyone may call).
+    /// A position is settled once claimed, refunded or swept, or once it has lost on a final market.
+    pub fn close_position(ctx: Context<ClosePosition>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let position = &ctx.accounts.position;
+
+        let lost = market.status == MarketStatus::Resolved
+            && position
+                .outcome
+                .is_some_and(|outcome| market.is_losing_outcome(outcome));
+        require!(position.claimed || lost, ErrorCode::PositionNotSettled);
+
+        market.open_positions -= 1;
+        let user_market = &mut ctx.accounts.user_market;
+        user_market.open_positions -= 1;
+        if user_market.is_closable(market) {
+            user_market.close(ctx.accounts.user.to_account_info())?;
+        }
+
+        emit!(PositionClosed {
+            user: position.user,
+            market: position.market,
+            position_index: position.index,
+        });
+
+        msg!("Position {} of {} closed", position.index, position.user);
+        Ok(())
+    }
+
+    /// Close a user's position counter once all their positions on a market are closed and
+    /// the market no longer takes bets, returning its rent to the user (anyone may call).
+    /// `close_position` does this itself when it closes the user's last position.
+    pub fn close_user_market(ctx: Context<CloseUserMarket>) -> Result<()> {
+        let user_market = &ctx.accounts.user_market;
+
+        require!(
+            user_market.is_closable(&ctx.accounts.market),
+            ErrorCode::UserMarketNotClosable
+        );
+
+        msg!("Position counter of {} closed", user_market.user);
+        Ok(())
+    }
+
+    /// Close a settled market and its vaults (anyone may call). Rent and the unused keeper
+    /// tip pool go back to whoever paid for the market; leftover stake (rounding dust, and
+    /// forfeits nobody could claim) goes to the treasury.
+    pub fn close_market(ctx: Context<CloseMarket>) -> Result<()> {
+        let market = &ctx.accounts.market;
+
+        // Every position, fee and dispute must be settled first
+        require!(
+            market.status == MarketStatus::Resolved || market.status == MarketStatus::Cancelled,
+            ErrorCode::MarketNotClosable
+        );
+        require!(market.open_positions == 0, ErrorCode::PositionsOutstanding);
+        require!(
+            market.fees_accrued == 0 && market.dispute_count == 0,
+            ErrorCode::MarketNotClosable
+        );
+
+        // A series' latest round is still needed to create its next round
+        if let Some(series_key) = market.series {
+            let series = ctx
+                .accounts
+                .series
+                .as_ref()
+                .ok_or(ErrorCode::MarketNotClosable)?;
+            require_keys_eq!(series.key(), series_key, ErrorCode::MarketNotClosable);
+            require!(
+                series.latest_market != Some(market.key()),
+                ErrorCode::MarketNotClosable
+            );
+        }
+
+        // SPL markets must close their token vault too
+        require!(
+            market.stake_mint.is_some() == ctx.accounts.token_vault.is_some(),
+            ErrorCode::InvalidStakeMint
+        );
+
+        let custody = MarketCustody::new(
+            market,
+            &ctx.accounts.vault,
+            &ctx.accounts.token_vault,
+            &ctx.accounts.token_program,
+            &ctx.accounts.system_program,
+        );
+        let treasury = ctx.accounts.treasury.to_account_info();
+        let rent_payer = ctx.accounts.rent_payer.to_account_info();
+
+        let mut leftover_stake = 0;
+        if let Some(token_vault) = &ctx.accounts.token_vault {
+            leftover_stake = token_vault.amount;
+            if leftover_stake > 0 {
+                custody.withdraw(
+                    &treasury,
+                    ctx.accounts.treasury_token_account.as_ref(),
+                    leftover_stake,
+                )?;
+            }
+            custody.close_token_vault(&rent_payer)?;
+        }
+
+        let (reserved, lamport_leftover) = market
+            .split_sol_vault(ctx.accounts.vault.lamports(), Rent::get()?.minimum_balance(0));
+        if lamport_leftover > 0 {
+            custody.withdraw_lamports(&treasury, lamport_leftover)?;
+        }
+        if market.stake_mint.is_none() {
+            leftover_stake = lamport_leftover;
+        }
+        custody.withdraw_lamports(&rent_payer, reserved)?;
+
+        let (dust, unclaimed_forfeits) = market.split_leftover_stake(leftover_stake);
+        emit!(MarketClosed {
+            market_id: market.market_id,
+            rent_payer: market.rent_payer,
+            dust,
+            unclaimed_forfeits,
+        });
+
+        msg!("Market {} closed", market.market_id);
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Account Structures
+// ============================================================================
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + ProgramConfig::INIT_SPACE,
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + MarketCounter::INIT_SPACE,
+        seeds = [b"market_counter"],
+        bump
+    )]
+    pub market_counter: Account<'info, MarketCounter>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()) @ ErrorCode::Unauthorized)]
+    pub program: Program<'info, crate::program::DarkbetPredictionMarket>,
+
+    /// Only the program's upgrade authority may create the config
+    #[account(constraint = program_data.upgrade_authority_address == Some(admin.key()) @ ErrorCode::Unauthorized)]
+    pub program_data: Account<'info, ProgramData>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(mut, seeds = [b"config"], bump = config.bump, has_one = admin)]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateMarket<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Market::INIT_SPACE,
+        seeds = [b"market", market_counter.next_market_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(mut, seeds = [b"market_counter"], bump = market_counter.bump)]
+    pub market_counter: Account<'info, MarketCounter>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// Lamport vault holding all stakes for this market, only the program can sign for it
+    #[account(
+        mut,
+        seeds = [b"vault", market.key().as_ref()],
+        bump
+    )]
+    pub vault: SystemAccount<'info>,
+
+    /// Mint stakes are denominated in; omit for a native SOL market
+    pub stake_mint: Option<Account<'info, Mint>>,
+
+    /// Token vault for SPL stakes, owned by the market PDA
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"token_vault", market.key().as_ref()],
+        bump,
+        token::mint = stake_mint,
+        token::authority = market
+    )]
+    pub token_vault: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub token_program: Option<Program<'info, Token>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_id: u64)]
+pub struct InitializeMarket<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Market::INIT_SPACE,
+        seeds = [b"market", market_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(mut, seeds = [b"market_counter"], bump = market_counter.bump)]
+    pub market_counter: Account<'info, MarketCounter>,
+
+    /// Explicit market IDs are reserved for the admin
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = config.admin == authority.key() @ ErrorCode::Unauthorized
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// Lamport vault holding all stakes for this market, only the program can sign for it
+    #[account(
+        mut,
+        seeds = [b"vault", market.key().as_ref()],
+        bump
+    )]
+    pub vault: SystemAccount<'info>,
+
+    /// Mint stakes are denominated in; omit for a native SOL market
+    pub stake_mint: Option<Account<'info, Mint>>,
+
+    /// Token vault for SPL stakes, owned by the market PDA
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"token_vault", market.key().as_ref()],
+        bump,
+        token::mint = stake_mint,
+        token::authority = market
+    )]
+    pub token_vault: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub token_program: Option<Program<'info, Token>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(series_id: u64)]
+pub struct CreateSeries<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + MarketSeries::INIT_SPACE,
+        seeds = [b"series", authority.key().as_ref(), series_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub series: Account<'info, MarketSeries>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateSeriesRound<'info> {
+    #[account(
+        mut,
+        seeds = [b"series", series.authority.as_ref(), series.series_id.to_le_bytes().as_ref()],
+        bump = series.bump
+    )]
+    pub series: Account<'info, MarketSeries>,
+
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + Market::INIT_SPACE,
+        seeds = [b"market", series_market_id(&series.key(), series.next_round).to_le_bytes().as_ref()],
+        bump
+    )]
+    pub market: Account<'info, Market>,
+
+    /// The series' latest round; omit when creating the first round
+    pub previous_market: Option<Account<'info, Market>>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// Lamport vault holding all stakes for this market, only the program can sign for it
+    #[account(
+        mut,
+        seeds = [b"vault", market.key().as_ref()],
+        bump
+    )]
+    pub vault: SystemAccount<'info>,
+
+    /// The series' stake mint; omit for a native SOL series
+    pub stake_mint: Option<Account<'info, Mint>>,
+
+    /// Token vault for SPL stakes, owned by the market PDA
+    #[account(
+        init,
+        payer = creator,
+        seeds = [b"token_vault", market.key().as_ref()],
+        bump,
+        token::mint = stake_mint,
+        token::authority = market
+    )]
+    pub token_vault: Option<Account<'info, TokenAccount>>,
+
+    /// Anyone can create the next round, paying its rent
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    pub token_program: Option<Program<'info, Token>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CommitBet<'info> {
+    /// Per user-market counter used to index the user's positions
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserMarket::INIT_SPACE,
+        seeds = [b"user_market", user.key().as_ref(), market.key().as_ref()],
+        bump
+    )]
+    pub user_market: Account<'info, UserMarket>,
+
+    #[account(
+        init,
+        payer = user,
+        space = 8 + UserPosition::INIT_SPACE,
+        seeds = [
+            b"position",
+            user.key().as_ref(),
+            market.key().as_ref(),
+            user_market.position_count.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub position: Account<'info, UserPosition>,
+
+    #[account(mut, seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(mut, seeds = [b"vault", market.key().as_ref()], bump = market.vault_bump)]
+    pub vault: SystemAccount<'info>,
+
+    #[account(mut, seeds = [b"token_vault", market.key().as_ref()], bump = market.token_vault_bump)]
+    pub token_vault: Option<Account<'info, TokenAccount>>,
+
+    /// User's token account for SPL markets
+    #[account(mut)]
+    pub user_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub token_program: Option<Program<'info, Token>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct TopUpCommitment<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"position",
+            user.key().as_ref(),
+            market.key().as_ref(),
+            position.index.to_le_bytes().as_ref()
+        ],
+        bump = position.bump,
+        has_one = user,
+        has_one = market
+    )]
+    pub position: Account<'info, UserPosition>,
+
+    #[account(seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(mut, seeds = [b"vault", market.key().as_ref()], bump = market.vault_bump)]
+    pub vault: SystemAccount<'info>,
+
+    #[account(mut, seeds = [b"token_vault", market.key().as_ref()], bump = market.token_vault_bump)]
+    pub token_vault: Option<Account<'info, TokenAccount>>,
+
+    /// User's token account for SPL markets
+    #[account(mut)]
+    pub user_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub token_program: Option<Program<'info, Token>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawCommitment<'info> {
+    #[account(
+        mut,
+        close = user,
+        seeds = [
+            b"position",
+            user.key().as_ref(),
+            market.key().as_ref(),
+            position.index.to_le_bytes().as_ref()
+        ],
+        bump = position.bump,
+        has_one = user,
+        has_one = market
+    )]
+    pub position: Account<'info, UserPosition>,
+
+    #[account(
+        mut,
+        seeds = [b"user_market", user.key().as_ref(), market.key().as_ref()],
+        bump = user_market.bump
+    )]
+    pub user_market: Account<'info, UserMarket>,
+
+    #[account(mut, seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(mut, seeds = [b"vault", market.key().as_ref()], bump = market.vault_bump)]
+    pub vault: SystemAccount<'info>,
+
+    #[account(mut, seeds = [b"token_vault", market.key().as_ref()], bump = market.token_vault_bump)]
+    pub token_vault: Option<Account<'info, TokenAccount>>,
+
+    /// User's token account for SPL markets
+    #[account(mut)]
+    pub user_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub token_program: Option<Program<'info, Token>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevealBet<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"position",
+            user.key().as_ref(),
+            market.key().as_ref(),
+            position.index.to_le_bytes().as_ref()
+        ],
+        bump = position.bump,
+        has_one = user,
+        has_one = market
+    )]
+    pub position: Account<'info, UserPosition>,
+
+    #[account(mut, seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    pub user: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RevealBetFor<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"position",
+            position.user.as_ref(),
+            market.key().as_ref(),
+            position.index.to_le_bytes().as_ref()
+        ],
+        bump = position.bump,
+        has_one = market
+    )]
+    pub position: Account<'info, UserPosition>,
+
+    #[account(mut, seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    /// CHECK: Instructions sysvar, holding the owner's ed25519 reveal signature
+    #[account(address = sysvar_instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+
+    /// Anyone holding the owner's signed reveal can submit it
+    pub relayer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct LockMarket<'info> {
+    #[account(mut, seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(mut, seeds = [b"vault", market.key().as_ref()], bump = market.vault_bump)]
+    pub vault: SystemAccount<'info>,
+
+    /// Anyone can call lock_market when the time is reached, earning the keeper tip
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveMarket<'info> {
+    #[account(mut, seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    /// CHECK: Pyth or Switchboard account; checked against the market's oracle source and parsed by its adapter
+    pub oracle_account: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Instructions sysvar, required for signed attestations
+    #[account(address = sysvar_instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+
+    #[account(mut, seeds = [b"vault", market.key().as_ref()], bump = market.vault_bump)]
+    pub vault: SystemAccount<'info>,
+
+    /// Anyone can call resolve_market once the resolution time is reached, earning the keeper tip
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveCategoricalMarket<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = config.resolver == resolver.key() || config.arbiter == resolver.key() @ ErrorCode::Unauthorized
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(mut, seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    /// Config resolver or arbiter, never the market authority
+    pub resolver: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ManualResolve<'info> {
+    #[account(seeds = [b"config"], bump = config.bump, has_one = resolver)]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(mut, seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    pub resolver: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ChallengeResolution<'info> {
+    #[account(mut, seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        init,
+        payer = challenger,
+        space = 8 + Dispute::INIT_SPACE,
+        seeds = [b"dispute", market.key().as_ref(), challenger.key().as_ref()],
+        bump
+    )]
+    pub dispute: Account<'info, Dispute>,
+
+    #[account(mut, seeds = [b"vault", market.key().as_ref()], bump = market.vault_bump)]
+    pub vault: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub challenger: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeResolution<'info> {
+    #[account(mut, seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(mut, seeds = [b"vault", market.key().as_ref()], bump = market.vault_bump)]
+    pub vault: SystemAccount<'info>,
+
+    /// Anyone can finalize an unchallenged resolution, earning the keeper tip
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ArbitrateResolution<'info> {
+    #[account(seeds = [b"config"], bump = config.bump, has_one = arbiter)]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(mut, seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    pub arbiter: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SettleDispute<'info> {
+    #[account(seeds = [b"config"], bump = config.bump, has_one = treasury)]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(mut, seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        seeds = [b"dispute", market.key().as_ref(), challenger.key().as_ref()],
+        bump = dispute.bump,
+        has_one = market,
+        has_one = challenger,
+        close = challenger
+    )]
+    pub dispute: Account<'info, Dispute>,
+
+    #[account(mut, seeds = [b"vault", market.key().as_ref()], bump = market.vault_bump)]
+    pub vault: SystemAccount<'info>,
+
+    /// CHECK: Challenger, receives the dispute rent and any returned bond; validated by `has_one`
+    #[account(mut)]
+    pub challenger: UncheckedAccount<'info>,
+
+    /// CHECK: Receives slashed bonds; validated by `has_one` on the config
+    #[account(mut)]
+    pub treasury: UncheckedAccount<'info>,
+
+    /// Anyone can settle disputes once the market is final
+    pub caller: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimWinnings<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"position",
+            user.key().as_ref(),
+            market.key().as_ref(),
+            position.index.to_le_bytes().as_ref()
+        ],
+        bump = position.bump,
+        has_one = user,
+        has_one = market
+    )]
+    pub position: Account<'info, UserPosition>,
+
+    #[account(mut, seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(mut, seeds = [b"vault", market.key().as_ref()], bump = market.vault_bump)]
+    pub vault: SystemAccount<'info>,
+
+    #[account(mut, seeds = [b"token_vault", market.key().as_ref()], bump = market.token_vault_bump)]
+    pub token_vault: Option<Account<'info, TokenAccount>>,
+
+    /// User's token account for SPL markets
+    #[account(mut)]
+    pub user_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub token_program: Option<Program<'info, Token>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelMarket<'info> {
+    #[account(mut, seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    /// Market authority, or anyone once the resolution grace period has passed
+    pub caller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RefundPosition<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"position",
+            user.key().as_ref(),
+            market.key().as_ref(),
+            position.index.to_le_bytes().as_ref()
+        ],
+        bump = position.bump,
+        has_one = user,
+        has_one = market
+    )]
+    pub position: Account<'info, UserPosition>,
+
+    #[account(seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(mut, seeds = [b"vault", market.key().as_ref()], bump = market.vault_bump)]
+    pub vault: SystemAccount<'info>,
+
+    #[account(mut, seeds = [b"token_vault", market.key().as_ref()], bump = market.token_vault_bump)]
+    pub token_vault: Option<Account<'info, TokenAccount>>,
+
+    /// User's token account for SPL markets
+    #[account(mut)]
+    pub user_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub token_program: Option<Program<'info, Token>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SweepUnrevealed<'info> {
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"position",
+            user.key().as_ref(),
+            market.key().as_ref(),
+            position.index.to_le_bytes().as_ref()
+        ],
+        bump = position.bump,
+        has_one = user,
+        has_one = market
+    )]
+    pub position: Account<'info, UserPosition>,
+
+    #[account(mut, seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(mut, seeds = [b"vault", market.key().as_ref()], bump = market.vault_bump)]
+    pub vault: SystemAccount<'info>,
+
+    #[account(mut, seeds = [b"token_vault", market.key().as_ref()], bump = market.token_vault_bump)]
+    pub token_vault: Option<Account<'info, TokenAccount>>,
+
+    /// CHECK: Position owner, receives any refund; validated by `has_one` on the position
+    #[account(mut)]
+    pub user: UncheckedAccount<'info>,
+
+    /// User's token account for SPL markets
+    #[account(mut)]
+    pub user_token_account: Option<Account<'info, TokenAccount>>,
+
+    /// CHECK: Only required for `ForfeitToTreasury`; checked against the config treasury
+    #[account(mut)]
+    pub treasury: Option<UncheckedAccount<'info>>,
+
+    /// Treasury's token account for SPL markets under `ForfeitToTreasury`
+    #[account(mut)]
+    pub treasury_token_account: Option<Account<'info, TokenAccount>>,
+
+    /// Anyone can sweep unrevealed positions once the market is resolved; the last sweep earns the keeper tip
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    pub token_program: Option<Program<'info, Token>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFees<'info> {
+    #[account(seeds = [b"config"], bump = config.bump, has_one = admin, has_one = treasury)]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(mut, seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(mut, seeds = [b"vault", market.key().as_ref()], bump = market.vault_bump)]
+    pub vault: SystemAccount<'info>,
+
+    #[account(mut, seeds = [b"token_vault", market.key().as_ref()], bump = market.token_vault_bump)]
+    pub token_vault: Option<Account<'info, TokenAccount>>,
+
+    /// CHECK: Fee recipient; validated by `has_one` on the config
+    #[account(mut)]
+    pub treasury: UncheckedAccount<'info>,
+
+    /// Treasury's token account for SPL markets
+    #[account(mut)]
+    pub treasury_token_account: Option<Account<'info, TokenAccount>>,
+
+    pub admin: Signer<'info>,
+
+    pub token_program: Option<Program<'info, Token>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClosePosition<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"position",
+            user.key().as_ref(),
+            market.key().as_ref(),
+            position.index.to_le_bytes().as_ref()
+        ],
+        bump = position.bump,
+        has_one = user,
+        has_one = market,
+        close = user
+    )]
+    pub position: Account<'info, UserPosition>,
+
+    #[account(
+        mut,
+        seeds = [b"user_market", user.key().as_ref(), market.key().as_ref()],
+        bump = user_market.bump
+    )]
+    pub user_market: Account<'info, UserMarket>,
+
+    #[account(mut, seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    /// CHECK: Position owner, receives the rent; validated by `has_one` on the position
+    #[account(mut)]
+    pub user: UncheckedAccount<'info>,
+
+    /// Anyone can close a settled position
+    pub caller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseUserMarket<'info> {
+    #[account(
+        mut,
+        seeds = [b"user_market", user.key().as_ref(), market.key().as_ref()],
+        bump = user_market.bump,
+        close = user
+    )]
+    pub user_market: Account<'info, UserMarket>,
+
+    #[account(seeds = [b"market", market.market_id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    /// CHECK: Counter owner, receives the rent; bound by the user_market seeds
+    #[account(mut)]
+    pub user: UncheckedAccount<'info>,
+
+    /// Anyone can close an unused counter
+    pub caller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseMarket<'info> {
+    #[account(seeds = [b"config"], bump = config.bump, has_one = treasury)]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"market", market.market_id.to_le_bytes().as_ref()],
+        bump = market.bump,
+        has_one = rent_payer,
+        close = rent_payer
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(mut, seeds = [b"vault", market.key().as_ref()], bump = market.vault_bump)]
+    pub vault: SystemAccount<'info>,
+
+    #[account(mut, seeds = [b"token_vault", market.key().as_ref()], bump = market.token_vault_bump)]
+    pub token_vault: Option<Account<'info, TokenAccount>>,
+
+    /// The market's series; required for series rounds
+    pub series: Option<Account<'info, MarketSeries>>,
+
+    /// CHECK: Paid for the market, receives its rent; validated by `has_one` on the market
+    #[account(mut)]
+    pub rent_payer: UncheckedAccount<'info>,
+
+    /// CHECK: Receives rounding dust; validated by `has_one` on the config
+    #[account(mut)]
+    pub treasury: UncheckedAccount<'info>,
+
+    /// Treasury's token account for SPL markets
+    #[account(mut)]
+    pub treasury_token_account: Option<Account<'info, TokenAccount>>,
+
+    /// Anyone can close a settled market
+    pub caller: Signer<'info>,
+
+    pub token_program: Option<Program<'info, Token>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// ============================================================================
+// Account State
+// ============================================================================
+
+#[account]
+#[derive(InitSpace)]
+pub struct ProgramConfig {
+    pub admin: Pubkey,               // 32
+    pub treasury: Pubkey,            // 32
+    pub fee_bps: u16,                // 2
+    pub min_stake_hundredths: u64,   // 8, in hundredths of a whole stake token
+    pub max_stake_hundredths: u64,   // 8
+    pub lock_buffer: i64,            // 8, seconds before resolution_time that betting locks
+    pub reveal_window: i64,          // 8, seconds after lock that reveals are accepted
+    pub resolution_grace_period: i64, // 8, seconds after resolution_time before anyone may cancel
+    pub early_exit_fee_bps: u16,     // 2, charged on withdraw_commitment
+    pub max_price_staleness: i64,    // 8, max seconds between oracle publish time and resolution_time
+    pub max_confidence_bps: u16,     // 2, max oracle confidence interval relative to the reference price
+    pub arbiter: Pubkey,             // 32, rules on challenged resolutions
+    pub dispute_window: i64,         // 8, seconds after resolution that it may be challenged
+    pub dispute_bond: u64,           // 8, lamports posted to challenge a resolution
+    pub arbitration_window: i64,     // 8, seconds after the dispute window for the arbiter to rule
+    pub resolver: Pubkey,            // 32, may resolve manually when the oracle times out
+    pub oracle_timeout: i64,         // 8, seconds after resolution_time before manual resolution
+    pub keeper_tip: u64,             // 8, lamports paid per successful crank call
+    pub keeper_tip_pool: u64,        // 8, lamports each market creator deposits to fund tips
+    pub bump: u8,                    // 1
+}
+
+impl ProgramConfig {
+    /// Validate and store a new set of parameters
+    pub fn apply(&mut self, params: &ConfigParams) -> Result<()> {
+        require!(
+            params.fee_bps <= MAX_FEE_BPS && params.early_exit_fee_bps <= MAX_FEE_BPS,
+            ErrorCode::FeeTooHigh
+        );
+        require!(
+            params.min_stake_hundredths > 0
+                && params.min_stake_hundredths <= params.max_stake_hundredths,
+            ErrorCode::InvalidConfig
+        );
+        // Reveals must close by resolution_time so nobody reveals after seeing the price
+        require!(
+            params.lock_buffer > 0
+                && params.reveal_window > 0
+                && params.reveal_window <= params.lock_buffer
+                && params.resolution_grace_period >= 0,
+            ErrorCode::InvalidConfig
+        );
+        require!(
+            params.max_price_staleness > 0
+                && params.max_confidence_bps > 0
+                && params.max_confidence_bps as u64 <= BPS_DENOMINATOR,
+            ErrorCode::InvalidConfig
+        );
+        require!(
+            params.dispute_window > 0 && params.dispute_bond > 0 && params.arbitration_window > 0,
+            ErrorCode::InvalidConfig
+        );
+        // The resolver gets a window between the oracle timeout and open cancellation
+        require!(
+            params.oracle_timeout > 0 && params.oracle_timeout < params.resolution_grace_period,
+            ErrorCode::InvalidConfig
+        );
+
+        self.treasury = params.treasury;
+        self.fee_bps = params.fee_bps;
+        self.min_stake_hundredths = params.min_stake_hundredths;
+        self.max_stake_hundredths = params.max_stake_hundredths;
+        self.lock_buffer = params.lock_buffer;
+        self.reveal_window = params.reveal_window;
+        self.resolution_grace_period = params.resolution_grace_period;
+        self.early_exit_fee_bps = params.early_exit_fee_bps;
+        self.max_price_staleness = params.max_price_staleness;
+        self.max_confidence_bps = params.max_confidence_bps;
+        self.arbiter = params.arbiter;
+        self.dispute_window = params.dispute_window;
+        self.dispute_bond = params.dispute_bond;
+        self.arbitration_window = params.arbitration_window;
+        self.resolver = params.resolver;
+        self.oracle_timeout = params.oracle_timeout;
+        self.keeper_tip = params.keeper_tip;
+        self.keeper_tip_pool = params.keeper_tip_pool;
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Market {
+    pub authority: Pubkey,           // 32
+    pub market_id: u64,              // 8
     pub asset_type: AssetType,       // 1 + size
     pub resolution_time: i64,        // 8
-    pub pyth_feed_account: Pubkey,   // 32
+    pub oracle_source: OracleSource, // 1 + 64
     pub threshold_price: i64,        // 8
-    pub total_long_stake: u64,       // 8
-    pub total_short_stake: u64,      // 8
     pub status: MarketStatus,        // 1 + size
     pub settlement_price: Option<i64>, // 1 + 8
     pub created_at: i64,             // 8
     pub bump: u8,                    // 1
+    pub vault_bump: u8,              // 1
+    pub unrevealed_policy: UnrevealedPolicy, // 1 + 2
+    pub unrevealed_positions: u64,   // 8
+    pub forfeited_stake: u64,        // 8
+    pub stake_mint: Option<Pubkey>,  // 1 + 32, None for native SOL
+    pub token_vault_bump: u8,        // 1
+    pub min_stake: u64,              // 8
+    pub max_stake: u64,              // 8
+    pub fee_bps: u16,                // 2, snapshot of the config fee at creation
+    pub fees_accrued: u64,           // 8, not yet withdrawn
+    pub lock_time: i64,              // 8, resolution_time minus the config lock buffer
+    pub reveal_deadline: i64,        // 8, lock_time plus the config reveal window
+    pub early_exit_fee_bps: u16,     // 2, snapshot of the config early-exit fee
+    pub market_type: MarketType,     // 1 + 16
+    pub outcome_count: u8,           // 1
+    pub outcome_stakes: [u64; MAX_OUTCOMES], // 8 * MAX_OUTCOMES, revealed stake per outcome
+    pub winning_outcome: Option<u8>, // 1 + 1
+    pub price_boundaries: [i64; MAX_OUTCOMES - 1], // 8 * 7, ascending bucket edges (PriceBuckets only)
+    pub price_expo: i32,             // 4, exponent the price feed must report
+    pub max_price_staleness: i64,    // 8, snapshot of the config staleness limit
+    pub max_confidence_bps: u16,     // 2, snapshot of the config confidence limit
+    pub dispute_window: i64,         // 8, snapshot of the config dispute window
+    pub dispute_bond: u64,           // 8, snapshot of the config dispute bond
+    pub arbitration_window: i64,     // 8, snapshot of the config arbitration window
+    pub dispute_deadline: i64,       // 8, end of the challenge period, set at provisional resolution
+    pub dispute_count: u32,          // 4, challenges posted against the resolution and not yet settled
+    pub resolution_overridden: bool, // 1, arbiter replaced the provisional resolution
+    pub arbitration_expired: bool,   // 1, arbiter missed its deadline; the resolution stood
+    pub manually_resolved: bool,     // 1, settled by the resolver instead of the oracle
+    pub keeper_tip: u64,             // 8, snapshot of the config keeper tip
+    pub keeper_tip_pool: u64,        // 8, lamports left in the vault for keeper tips
+    pub rent_payer: Pubkey,          // 32, paid for the market and its vaults, refunded on close
+    pub open_positions: u64,         // 8, positions not yet closed
+    pub series: Option<Pubkey>,      // 1 + 32, series this market is a round of
+    pub resolution_grace_period: i64, // 8, snapshot of the config cancellation grace period
+    pub oracle_timeout: i64,         // 8, snapshot of the config oracle timeout
+}
+
+impl Market {
+    /// Validate `params` and set up a fresh, open market, snapshotting the config.
+    /// Bumps and custody are set separately by the creating instruction.
+    pub fn initialize(
+        &mut self,
+        market_id: u64,
+        authority: Pubkey,
+        params: MarketParams,
+        config: &ProgramConfig,
+        now: i64,
+    ) -> Result<()> {
+        params.validate(now, config.lock_buffer)?;
+        let MarketParams {
+            asset_type,
+            resolution_time,
+            oracle_source,
+            threshold_price,
+            unrevealed_policy,
+            market_type,
+            price_boundaries,
+            price_expo,
+        } = params;
+
+        self.authority = authority;
+        self.market_id = market_id;
+        self.asset_type = asset_type;
+        self.resolution_time = resolution_time;
+        self.oracle_source = oracle_source;
+        self.threshold_price = threshold_price;
+        self.market_type = market_type;
+        self.outcome_count = market_type.outcome_count();
+        self.outcome_stakes = [0; MAX_OUTCOMES];
+        self.winning_outcome = None;
+        self.price_boundaries = [0; MAX_OUTCOMES - 1];
+        self.price_boundaries[..price_boundaries.len()].copy_from_slice(&price_boundaries);
+        self.status = MarketStatus::Open;
+        self.settlement_price = None;
+        self.created_at = now;
+        self.unrevealed_policy = unrevealed_policy;
+        self.unrevealed_positions = 0;
+        self.forfeited_stake = 0;
+        self.fee_bps = config.fee_bps;
+        self.fees_accrued = 0;
+        self.early_exit_fee_bps = config.early_exit_fee_bps;
+        self.lock_time = resolution_time - config.lock_buffer;
+        self.reveal_deadline = self.lock_time + config.reveal_window;
+        self.price_expo = price_expo;
+        self.max_price_staleness = config.max_price_staleness;
+        self.max_confidence_bps = config.max_confidence_bps;
+        self.dispute_window = config.dispute_window;
+        self.dispute_bond = config.dispute_bond;
+        self.arbitration_window = config.arbitration_window;
+        self.dispute_deadline = 0;
+        self.dispute_count = 0;
+        self.resolution_overridden = false;
+        self.arbitration_expired = false;
+        self.manually_resolved = false;
+        self.keeper_tip = config.keeper_tip;
+        self.keeper_tip_pool = config.keeper_tip_pool;
+        self.resolution_grace_period = config.resolution_grace_period;
+        self.oracle_timeout = config.oracle_timeout;
+        self.open_positions = 0;
+        self.series = None;
+        Ok(())
+    }
+
+    /// Record where stakes are held and scale the config stake bounds to the stake token.
+    /// SPL markets need the mint, token vault and token program together; SOL markets need none.
+    pub fn set_custody(
+        &mut self,
+        stake_mint: Option<&Account<Mint>>,
+        token_vault: &Option<Account<TokenAccount>>,
+        token_program: &Option<Program<Token>>,
+        token_vault_bump: u8,
+        config: &ProgramConfig,
+    ) -> Result<()> {
+        require!(
+            stake_mint.is_some() == token_vault.is_some()
+                && stake_mint.is_some() == token_program.is_some(),
+            ErrorCode::InvalidStakeMint
+        );
+        self.stake_mint = stake_mint.map(|mint| mint.key());
+        self.token_vault_bump = token_vault_bump;
+
+        let decimals = stake_mint.map_or(SOL_DECIMALS, |mint| mint.decimals);
+        (self.min_stake, self.max_stake) = stake_bounds(
+            decimals,
+            config.min_stake_hundredths,
+            config.max_stake_hundredths,
+        )?;
+        Ok(())
+    }
+
+    /// Winning outcome for a settlement price on a price-settled market.
+    /// Scalar markets have no single winner and return None.
+    pub fn outcome_for_price(&self, price: i64) -> Result<Option<u8>> {
+        match self.market_type {
+            // Long wins only if the price settles strictly above the threshold
+            MarketType::Binary => Ok(Some(if price > self.threshold_price {
+                Direction::Long.outcome()
+            } else {
+                Direction::Short.outcome()
+            })),
+            MarketType::PriceBuckets { bucket_count } => Ok(Some(find_bucket(
+                &self.price_boundaries[..bucket_count as usize - 1],
+                price,
+            ))),
+            MarketType::Scalar { .. } => Ok(None),
+            MarketType::Categorical { .. } => err!(ErrorCode::MarketTypeMismatch),
+        }
+    }
+
+    /// Reject oracle prices that are stale, too uncertain, or in the wrong exponent.
+    /// Binary markets measure confidence against the threshold; other markets against the price itself.
+    pub fn validate_settlement_price(&self, price: &OraclePrice) -> Result<()> {
+        let reference_price = match self.market_type {
+            MarketType::Binary => self.threshold_price,
+            _ => price.price,
+        };
+        check_oracle_price(
+            price,
+            self.price_expo,
+            self.resolution_time,
+            self.max_price_staleness,
+            self.max_confidence_bps,
+            reference_price,
+        )
+    }
+
+    /// Check a new commitment against the market's timing and stake bounds and count it as
+    /// unrevealed and open. The caller deposits the stake.
+    pub fn commit(&mut self, stake_amount: u64, now: i64) -> Result<()> {
+        self.require_accepting_bets(now)?;
+        require!(stake_amount >= self.min_stake, ErrorCode::StakeTooLow);
+        require!(stake_amount <= self.max_stake, ErrorCode::StakeTooHigh);
+
+        self.unrevealed_positions = self
+            .unrevealed_positions
+            .checked_add(1)
+            .ok_or(ErrorCode::MathOverflow)?;
+        self.open_positions = self
+            .open_positions
+            .checked_add(1)
+            .ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+
+    /// New total stake of a commitment topped up by `additional_stake`, checked against the
+    /// market's timing and stake bounds. The caller deposits the additional stake.
+    pub fn top_up(&self, stake_amount: u64, additional_stake: u64, now: i64) -> Result<u64> {
+        self.require_accepting_bets(now)?;
+
+        let new_stake = stake_amount
+            .checked_add(additional_stake)
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(
+            additional_stake > 0 && new_stake >= self.min_stake,
+            ErrorCode::StakeTooLow
+        );
+        require!(new_stake <= self.max_stake, ErrorCode::StakeTooHigh);
+        Ok(new_stake)
+    }
+
+    /// Remove an unrevealed commitment before lock: returns the refund and the early-exit fee.
+    /// The fee is accrued to the market and stays in custody until withdrawn.
+    pub fn withdraw_commitment(&mut self, stake_amount: u64, now: i64) -> Result<(u64, u64)> {
+        self.require_accepting_bets(now)?;
+
+        let fee = apply_bps(stake_amount, self.early_exit_fee_bps)?;
+        self.fees_accrued = self
+            .fees_accrued
+            .checked_add(fee)
+            .ok_or(ErrorCode::MathOverflow)?;
+        self.unrevealed_positions -= 1;
+        self.open_positions -= 1;
+        Ok((stake_amount - fee, fee))
+    }
+
+    fn require_accepting_bets(&self, now: i64) -> Result<()> {
+        require!(self.status == MarketStatus::Open, ErrorCode::MarketNotOpen);
+        require!(now < self.lock_time, ErrorCode::MarketLocked);
+        Ok(())
+    }
+
+    /// Stake returned to a position on a cancelled market, revealed or not
+    pub fn refund(&self, stake_amount: u64) -> Result<u64> {
+        require!(
+            self.status == MarketStatus::Cancelled,
+            ErrorCode::MarketNotCancelled
+        );
+        Ok(stake_amount)
+    }
+
+    /// Settle an unrevealed position under the market's policy once the reveal window has
+    /// closed. Forfeits shared by the winners stay in custody as `forfeited_stake`; the
+    /// caller pays out the refund and the treasury's share.
+    pub fn sweep(&mut self, stake_amount: u64) -> Result<SweptStake> {
+        require!(
+            self.status == MarketStatus::Provisional || self.status == MarketStatus::Resolved,
+            ErrorCode::MarketNotResolved
+        );
+
+        let swept = match self.unrevealed_policy {
+            UnrevealedPolicy::ForfeitToWinners => SweptStake {
+                refunded: 0,
+                forfeited: stake_amount,
+                to_treasury: 0,
+            },
+            UnrevealedPolicy::ForfeitToTreasury => SweptStake {
+                refunded: 0,
+                forfeited: stake_amount,
+                to_treasury: stake_amount,
+            },
+            UnrevealedPolicy::RefundWithPenalty { penalty_bps } => {
+                let penalty = apply_bps(stake_amount, penalty_bps)?;
+                SweptStake {
+                    refunded: stake_amount - penalty,
+                    forfeited: penalty,
+                    to_treasury: 0,
+                }
+            }
+        };
+
+        self.forfeited_stake = self
+            .forfeited_stake
+            .checked_add(swept.forfeited - swept.to_treasury)
+            .ok_or(ErrorCode::MathOverflow)?;
+        self.unrevealed_positions -= 1;
+        Ok(swept)
+    }
+
+    /// Record a challenge against the provisional resolution, before the dispute deadline
+    pub fn challenge(&mut self, now: i64) -> Result<()> {
+        require!(
+            self.status == MarketStatus::Provisional,
+            ErrorCode::ResolutionNotProvisional
+        );
+        require!(now < self.dispute_deadline, ErrorCode::DisputeWindowClosed);
+
+        self.dispute_count = self
+            .dispute_count
+            .checked_add(1)
+            .ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Make the provisional resolution final once the dispute window closes. A challenged
+    /// resolution is left to the arbiter until the arbitration deadline, then stands as it is.
+    pub fn finalize(&mut self, now: i64) -> Result<()> {
+        require!(
+            self.status == MarketStatus::Provisional,
+            ErrorCode::ResolutionNotProvisional
+        );
+        require!(now >= self.dispute_deadline, ErrorCode::DisputeWindowOpen);
+
+        if self.dispute_count > 0 {
+            require!(now >= self.arbitration_deadline()?, ErrorCode::DisputesPending);
+            self.arbitration_expired = true;
+        }
+
+        self.status = MarketStatus::Resolved;
+        Ok(())
+    }
+
+    /// Apply the arbiter's ruling on a challenged resolution, making it final
+    pub fn arbitrate(&mut self, ruling: ArbiterRuling, now: i64) -> Result<()> {
+        require!(
+            self.status == MarketStatus::Provisional,
+            ErrorCode::ResolutionNotProvisional
+        );
+        require!(now >= self.dispute_deadline, ErrorCode::DisputeWindowOpen);
+        require!(self.dispute_count > 0, ErrorCode::NoDisputes);
+        require!(
+            now < self.arbitration_deadline()?,
+            ErrorCode::ArbitrationWindowClosed
+        );
+
+        match ruling {
+            ArbiterRuling::Confirm => {}
+            // Price-settled markets are re-settled at the corrected price
+            ArbiterRuling::OverridePrice { settlement_price } => {
+                self.winning_outcome = self.outcome_for_price(settlement_price)?;
+                self.settlement_price = Some(settlement_price);
+                self.resolution_overridden = true;
+            }
+            ArbiterRuling::OverrideOutcome { winning_outcome } => {
+                require!(
+                    matches!(self.market_type, MarketType::Categorical { .. }),
+                    ErrorCode::MarketTypeMismatch
+                );
+                require!(
+                    winning_outcome < self.outcome_count,
+                    ErrorCode::InvalidOutcome
+                );
+                self.winning_outcome = Some(winning_outcome);
+                self.resolution_overridden = true;
+            }
+        }
+
+        self.status = MarketStatus::Resolved;
+        Ok(())
+    }
+
+    /// Settle one dispute on a final market: whether its bond goes back to the challenger
+    /// (the arbiter overrode the resolution or never ruled) rather than to the treasury
+    pub fn settle_dispute(&mut self) -> Result<bool> {
+        require!(
+            self.status == MarketStatus::Resolved,
+            ErrorCode::MarketNotResolved
+        );
+        require!(self.dispute_count > 0, ErrorCode::NoDisputes);
+
+        self.dispute_count -= 1;
+        Ok(self.resolution_overridden || self.arbitration_expired)
+    }
+
+    /// Split the SOL vault's balance at close into what returns to the rent payer (the
+    /// vault's rent and the unused tip pool) and leftover stake
+    pub fn split_sol_vault(&self, vault_balance: u64, rent_minimum: u64) -> (u64, u64) {
+        let reserved = rent_minimum.saturating_add(self.keeper_tip_pool);
+        let leftover_stake = vault_balance.saturating_sub(reserved);
+        (vault_balance - leftover_stake, leftover_stake)
+    }
+
+    /// Whether a revealed outcome is owed nothing on a resolved market.
+    /// Scalar markets can pay both sides, and a market nobody won refunds every
+    /// revealed stake, so those positions settle by claiming.
+    pub fn is_losing_outcome(&self, outcome: u8) -> bool {
+        match self.market_type {
+            MarketType::Scalar { .. } => false,
+            _ if self.has_no_winners() => false,
+            _ => self.winning_outcome.is_some_and(|winning| winning != outcome),
+        }
+    }
+
+    /// Whether the market resolved to an outcome that no revealed stake backed
+    pub fn has_no_winners(&self) -> bool {
+        match self.market_type {
+            MarketType::Scalar { .. } => false,
+            _ => self
+                .winning_outcome
+                .is_some_and(|winning| self.outcome_stakes[winning as usize] == 0),
+        }
+    }
+
+    /// Settle a claim for a revealed position: returns the payout and the protocol fee,
+    /// and accrues the fee to the market.
+    pub fn claim(&mut self, outcome: u8, stake_amount: u64) -> Result<(u64, u64)> {
+        require!(
+            self.status == MarketStatus::Resolved,
+            ErrorCode::MarketNotResolved
+        );
+        // Every unrevealed stake must be swept so the pools are final
+        require!(
+            self.unrevealed_positions == 0,
+            ErrorCode::UnrevealedPositionsPending
+        );
+
+        let gross_payout = self.gross_payout(outcome, stake_amount)?;
+
+        // Protocol fee is taken from the winnings only, never from the returned stake
+        let fee = apply_bps(gross_payout.saturating_sub(stake_amount), self.fee_bps)?;
+
+        self.fees_accrued = self
+            .fees_accrued
+            .checked_add(fee)
+            .ok_or(ErrorCode::MathOverflow)?;
+        Ok((gross_payout - fee, fee))
+    }
+
+    /// Stake plus winnings owed to a revealed position on a resolved market, before fees
+    pub fn gross_payout(&self, outcome: u8, stake_amount: u64) -> Result<u64> {
+        let total_pot = self
+            .total_revealed_stake()?
+            .checked_add(self.forfeited_stake)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        match self.market_type {
+            // Both sides are paid according to where the settlement price lands
+            MarketType::Scalar { lower_bound, upper_bound } => {
+                let settlement_price = self.settlement_price.ok_or(ErrorCode::MarketNotResolved)?;
+                let long_pool = self.outcome_stakes[Direction::Long.outcome() as usize];
+                let short_pool = self.outcome_stakes[Direction::Short.outcome() as usize];
+                let (long_allocation, short_allocation) = scalar_allocations(
+                    total_pot,
+                    long_pool,
+                    short_pool,
+                    lower_bound,
+                    upper_bound,
+                    settlement_price,
+                );
+
+                if outcome == Direction::Long.outcome() {
+                    pro_rata_share(long_allocation, stake_amount, long_pool)
+                } else {
+                    pro_rata_share(short_allocation, stake_amount, short_pool)
+                }
+            }
+            // Nobody backed the winning outcome: settle like a cancellation and refund the stake
+            _ if self.has_no_winners() => Ok(stake_amount),
+            // Every other outcome's pool, plus forfeited stakes, is shared by the winners
+            _ => {
+                let winning_outcome = self.winning_outcome.ok_or(ErrorCode::MarketNotResolved)?;
+                require!(outcome == winning_outcome, ErrorCode::NotAWinner);
+
+                let winning_pool = self.outcome_stakes[winning_outcome as usize];
+                calculate_payout(stake_amount, winning_pool, total_pot - winning_pool)
+            }
+        }
+    }
+
+    /// Forfeited stake that no claim can pay out: on a resolved market where no revealed
+    /// position shares the pot, forfeits and penalties stay in the vault until close
+    pub fn unclaimable_forfeits(&self) -> u64 {
+        if self.status != MarketStatus::Resolved {
+            return 0;
+        }
+        let pot_paid_out = match self.market_type {
+            MarketType::Scalar { .. } => self.outcome_stakes.iter().any(|stake| *stake > 0),
+            _ => !self.has_no_winners(),
+        };
+        if pot_paid_out {
+            0
+        } else {
+            self.forfeited_stake
+        }
+    }
+
+    /// Split the stake left in the vault at close into payout rounding dust and
+    /// unclaimable forfeits
+    pub fn split_leftover_stake(&self, leftover_stake: u64) -> (u64, u64) {
+        let unclaimed_forfeits = self.unclaimable_forfeits().min(leftover_stake);
+        (leftover_stake - unclaimed_forfeits, unclaimed_forfeits)
+    }
+
+    /// Last moment the arbiter may rule on a challenged resolution
+    pub fn arbitration_deadline(&self) -> Result<i64> {
+        self.dispute_deadline
+            .checked_add(self.arbitration_window)
+            .ok_or_else(|| error!(ErrorCode::MathOverflow))
+    }
+
+    /// Revealed stake across all outcomes
+    pub fn total_revealed_stake(&self) -> Result<u64> {
+        self.outcome_stakes
+            .iter()
+            .try_fold(0u64, |total, stake| total.checked_add(*stake))
+            .ok_or_else(|| error!(ErrorCode::MathOverflow))
+    }
+}
+
+/// Where an unrevealed position's stake goes when it is swept
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SweptStake {
+    pub refunded: u64,    // Paid back to the position owner
+    pub forfeited: u64,   // Kept from the owner
+    pub to_treasury: u64, // Share of the forfeit paid to the treasury; the rest goes to winners
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct UserPosition {
+    pub user: Pubkey,                // 32
+    pub market: Pubkey,              // 32
+    pub stake_amount: u64,           // 8
+    pub commitment_hash: [u8; 32],   // 32
+    pub outcome: Option<u8>,         // 1 + 1
+    pub revealed: bool,              // 1
+    pub claimed: bool,               // 1
+    pub committed_at: i64,           // 8
+    pub bump: u8,                    // 1
+    pub index: u64,                  // 8, position number within the user's UserMarket
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct UserMarket {
+    pub user: Pubkey,                // 32
+    pub market: Pubkey,              // 32
+    pub position_count: u64,         // 8, next position index
+    pub open_positions: u64,         // 8, positions not yet closed
+    pub bump: u8,                    // 1
+}
+
+impl UserMarket {
+    /// Whether the counter can be closed: every position is closed and the market takes no more
+    /// commits, so position indices are never reused
+    pub fn is_closable(&self, market: &Market) -> bool {
+        self.open_positions == 0 && market.status != MarketStatus::Open
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct MarketCounter {
+    pub next_market_id: u64,         // 8, assigned to the next create_market call
+    pub bump: u8,                    // 1
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct MarketSeries {
+    pub authority: Pubkey,           // 32, becomes the authority of every round
+    pub series_id: u64,              // 8
+    pub template: SeriesTemplate,    // size of the template
+    pub next_round: u64,             // 8, index of the next round to create
+    pub latest_market: Option<Pubkey>, // 1 + 32, most recent round
+    pub latest_resolution_time: i64, // 8, resolution time of the most recent round
+    pub bump: u8,                    // 1
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Dispute {
+    pub market: Pubkey,              // 32
+    pub challenger: Pubkey,          // 32
+    pub bond: u64,                   // 8, lamports held in the market vault
+    pub challenged_at: i64,          // 8
+    pub bump: u8,                    // 1
+}
+
+// ============================================================================
+// Instruction Parameters
+// ============================================================================
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct ConfigParams {
+    pub treasury: Pubkey,
+    pub fee_bps: u16,
+    pub min_stake_hundredths: u64,
+    pub max_stake_hundredths: u64,
+    pub lock_buffer: i64,
+    pub reveal_window: i64,
+    pub resolution_grace_period: i64,
+    pub early_exit_fee_bps: u16,
+    pub max_price_staleness: i64,
+    pub max_confidence_bps: u16,
+    pub arbiter: Pubkey,
+    pub dispute_window: i64,
+    pub dispute_bond: u64,
+    pub arbitration_window: i64,
+    pub resolver: Pubkey,
+    pub oracle_timeout: i64,
+    pub keeper_tip: u64,
+    pub keeper_tip_pool: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct MarketParams {
+    pub asset_type: AssetType,
+    pub resolution_time: i64,
+    pub oracle_source: OracleSource,
+    pub threshold_price: i64,
+    pub unrevealed_policy: UnrevealedPolicy,
+    pub market_type: MarketType,
+    pub price_boundaries: Vec<i64>, // Ascending bucket edges, PriceBuckets only
+    pub price_expo: i32,            // Exponent of the price feed, e.g. -8
+}
+
+impl MarketParams {
+    /// Check the parameters describe a well-formed market that locks after `now`
+    pub fn validate(&self, now: i64, lock_buffer: i64) -> Result<()> {
+        // Validate the market still has a betting window before it locks
+        require!(
+            self.resolution_time.saturating_sub(lock_buffer) > now,
+            ErrorCode::InvalidResolutionTime
+        );
+
+        // Validate penalty is at most 100%
+        if let UnrevealedPolicy::RefundWithPenalty { penalty_bps } = self.unrevealed_policy {
+            require!(
+                penalty_bps as u64 <= BPS_DENOMINATOR,
+                ErrorCode::InvalidBasisPoints
+            );
+        }
+
+        // Validate outcome count
+        let outcome_count = self.market_type.outcome_count();
+        require!(
+            (2..=MAX_OUTCOMES as u8).contains(&outcome_count),
+            ErrorCode::InvalidOutcomeCount
+        );
+
+        // Bucket markets need one ascending boundary between each pair of buckets
+        let boundary_count = match self.market_type {
+            MarketType::PriceBuckets { bucket_count } => bucket_count as usize - 1,
+            _ => 0,
+        };
+        require!(
+            self.price_boundaries.len() == boundary_count
+                && self.price_boundaries.windows(2).all(|pair| pair[0] < pair[1]),
+            ErrorCode::InvalidPriceBoundaries
+        );
+
+        // Scalar markets need a non-empty price range
+        if let MarketType::Scalar { lower_bound, upper_bound } = self.market_type {
+            require!(lower_bound < upper_bound, ErrorCode::InvalidScalarBounds);
+        }
+        Ok(())
+    }
+}
+
+/// Parameters shared by every round of a market series; rounds are binary markets
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Debug)]
+pub struct SeriesTemplate {
+    pub asset_type: AssetType,
+    pub oracle_source: OracleSource,
+    pub price_expo: i32,
+    pub interval: i64,              // Seconds between consecutive rounds' resolution times
+    pub threshold_rule: ThresholdRule,
+    pub unrevealed_policy: UnrevealedPolicy,
+    pub stake_mint: Option<Pubkey>, // None for native SOL
+}
+
+impl SeriesTemplate {
+    /// Market parameters for a round resolving at `resolution_time` against `threshold_price`
+    pub fn market_params(&self, resolution_time: i64, threshold_price: i64) -> MarketParams {
+        MarketParams {
+            asset_type: self.asset_type,
+            resolution_time,
+            oracle_source: self.oracle_source,
+            threshold_price,
+            unrevealed_policy: self.unrevealed_policy,
+            market_type: MarketType::Binary,
+            price_boundaries: Vec::new(),
+            price_expo: self.price_expo,
+        }
+    }
+}
+
+// ============================================================================
+// Enums
+// ============================================================================
+
+/// How each round of a series picks its threshold price
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Debug)]
+pub enum ThresholdRule {
+    Fixed { price: i64 },                  // Every round uses the same threshold
+    LastSettlement { initial_price: i64 }, // Previous round's settlement price; initial_price for the first
+}
+
+impl ThresholdRule {
+    /// Threshold of a series' first round
+    pub fn initial_price(&self) -> i64 {
+        match *self {
+            ThresholdRule::Fixed { price } => price,
+            ThresholdRule::LastSettlement { initial_price } => initial_price,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace)]
+pub enum MarketStatus {
+    Open,
+    Locked,
+    Provisional, // Settled, but still open to challenge
+    Resolved,    // Final; claims are enabled
+    Cancelled,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Debug)]
+pub enum Direction {
+    Long,  // Betting price will be ABOVE threshold
+    Short, // Betting price will be BELOW threshold
+}
+
+impl Direction {
+    /// Outcome index of this side in a binary market
+    pub fn outcome(self) -> u8 {
+        match self {
+            Direction::Long => 0,
+            Direction::Short => 1,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Debug)]
+pub enum MarketType {
+    Binary,                          // Long (outcome 0) vs Short (outcome 1), settled by the price feed
+    Categorical { outcome_count: u8 }, // N outcomes, settled by the config resolver or arbiter
+    PriceBuckets { bucket_count: u8 }, // Ordered price bands, settled by the price feed
+    Scalar { lower_bound: i64, upper_bound: i64 }, // Long/Short paid linearly across the range
+}
+
+impl MarketType {
+    pub fn outcome_count(&self) -> u8 {
+        match self {
+            MarketType::Binary | MarketType::Scalar { .. } => 2,
+            MarketType::Categorical { outcome_count } => *outcome_count,
+            MarketType::PriceBuckets { bucket_count } => *bucket_count,
+        }
+    }
+}
+
+/// Where a price-settled market reads its settlement price
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Debug)]
+pub enum OracleSource {
+    Pyth { price_account: Pubkey },        // Pyth v2 price account
+    PythPull { feed_id: [u8; 32] },        // Any fully verified Pyth PriceUpdateV2 for this feed
+    Switchboard { aggregator: Pubkey },    // Switchboard v2 aggregator account
+    SignedAttestation { signer: Pubkey, feed_id: [u8; 32] }, // ed25519-signed price, see ATTESTATION_DOMAIN
+}
+
+/// Arbiter's decision on a challenged resolution
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ArbiterRuling {
+    Confirm,                              // Provisional resolution stands, bonds are slashed
+    OverridePrice { settlement_price: i64 }, // Re-settle a price-based market, bonds are returned
+    OverrideOutcome { winning_outcome: u8 }, // Replace a categorical outcome, bonds are returned
+}
+
+/// What happens to a position that is not revealed before resolution_time
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Debug)]
+pub enum UnrevealedPolicy {
+    ForfeitToWinners,                      // Stake is added to the losing pool
+    ForfeitToTreasury,                     // Stake is sent to the config treasury
+    RefundWithPenalty { penalty_bps: u16 }, // Stake is refunded, penalty goes to the winners
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Debug)]
+pub enum AssetType {
+    BTC,
+    ETH,
+    SOL,
+    BNB,
+}
+
+// ============================================================================
+// Events
+// ============================================================================
+
+#[event]
+pub struct ConfigUpdated {
+    pub admin: Pubkey,
+    pub params: ConfigParams,
+}
+
+#[event]
+pub struct MarketCreated {
+    pub market_id: u64,
+    pub authority: Pubkey,
+    pub asset_type: AssetType,
+    pub resolution_time: i64,
+    pub threshold_price: i64,
+    pub market_type: MarketType,
+}
+
+#[event]
+pub struct SeriesCreated {
+    pub series: Pubkey,
+    pub series_id: u64,
+    pub authority: Pubkey,
+    pub template: SeriesTemplate,
+    pub first_resolution_time: i64,
+}
+
+#[event]
+pub struct SeriesRoundCreated {
+    pub series: Pubkey,
+    pub round: u64,
+    pub market: Pubkey,
+    pub market_id: u64,
+    pub created_by: Pubkey,
+}
+
+#[event]
+pub struct BetCommitted {
+    pub user: Pubkey,
+    pub market: Pubkey,
+    pub position_index: u64,
+    pub stake_amount: u64,
+    pub commitment_hash: [u8; 32],
+}
+
+#[event]
+pub struct CommitmentToppedUp {
+    pub user: Pubkey,
+    pub market: Pubkey,
+    pub position_index: u64,
+    pub additional_stake: u64,
+    pub stake_amount: u64,
+    pub commitment_hash: [u8; 32],
+}
+
+#[event]
+pub struct CommitmentWithdrawn {
+    pub user: Pubkey,
+    pub market: Pubkey,
+    pub position_index: u64,
+    pub stake_amount: u64,
+    pub refunded: u64,
+    pub fee: u64,
+}
+
+#[event]
+pub struct BetRevealed {
+    pub user: Pubkey,
+    pub market: Pubkey,
+    pub outcome: u8,
+    pub stake_amount: u64,
+    pub revealed_by: Pubkey, // The user, or the relayer for reveal_bet_for
+}
+
+#[event]
+pub struct MarketLocked {
+    pub market_id: u64,
+    pub lock_time: i64,
+}
+
+#[event]
+pub struct MarketResolved {
+    pub market_id: u64,
+    pub settlement_price: Option<i64>,
+    pub threshold_price: i64,
+    pub winning_outcome: Option<u8>, // None for scalar markets
+    pub resolved_at: i64,
+    pub dispute_deadline: i64,
+    pub manual: bool, // Settled by the resolver after an oracle timeout
+}
+
+#[event]
+pub struct ResolutionChallenged {
+    pub market: Pubkey,
+    pub challenger: Pubkey,
+    pub bond: u64,
+    pub challenged_at: i64,
+}
+
+#[event]
+pub struct ResolutionFinalized {
+    pub market_id: u64,
+    pub settlement_price: Option<i64>,
+    pub winning_outcome: Option<u8>,
+    pub overridden: bool,
+    pub finalized_at: i64,
+}
+
+#[event]
+pub struct DisputeSettled {
+    pub market: Pubkey,
+    pub challenger: Pubkey,
+    pub bond: u64,
+    pub returned: bool,
+}
+
+#[event]
+pub struct KeeperTipPaid {
+    pub market: Pubkey,
+    pub keeper: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct WinningsClaimed {
+    pub user: Pubkey,
+    pub market: Pubkey,
+    pub stake_amount: u64,
+    pub payout: u64,
+    pub fee: u64,
+}
+
+#[event]
+pub struct MarketCancelled {
+    pub market_id: u64,
+    pub cancelled_by: Pubkey,
+    pub oracle_timeout: bool,
+    pub cancelled_at: i64,
+}
+
+#[event]
+pub struct PositionRefunded {
+    pub user: Pubkey,
+    pub market: Pubkey,
+    pub stake_amount: u64,
+}
+
+#[event]
+pub struct PositionClosed {
+    pub user: Pubkey,
+    pub market: Pubkey,
+    pub position_index: u64,
+}
+
+#[event]
+pub struct MarketClosed {
+    pub market_id: u64,
+    pub rent_payer: Pubkey,
+    pub dust: u64,               // Payout rounding leftovers sent to the treasury, in the stake token
+    pub unclaimed_forfeits: u64, // Forfeited stake nobody could claim, sent to the treasury
+}
+
+#[event]
+pub struct FeesCollected {
+    pub market: Pubkey,
+    pub treasury: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct UnrevealedSwept {
+    pub user: Pubkey,
+    pub market: Pubkey,
+    pub policy: UnrevealedPolicy,
+    pub stake_amount: u64,
+    pub refunded: u64,
+    pub forfeited: u64,
+}
+
+// ============================================================================
+// Error Codes
+// ============================================================================
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Resolution time must be in the future")]
+    InvalidResolutionTime,
+
+    #[msg("Market is not open for betting")]
+    MarketNotOpen,
+
+    #[msg("Market is locked, no new bets allowed")]
+    MarketLocked,
+
+    #[msg("Stake amount is below the market minimum")]
+    StakeTooLow,
+
+    #[msg("Stake amount is above the market maximum")]
+    StakeTooHigh,
+
+    #[msg("Market is not locked yet")]
+    MarketNotLocked,
+
+    #[msg("Reveal window is closed")]
+    RevealWindowClosed,
+
+    #[msg("Bet already revealed")]
+    AlreadyRevealed,
+
+    #[msg("Invalid commitment hash")]
+    InvalidCommitment,
+
+    #[msg("Lock time has not been reached")]
+    LockTimeNotReached,
+
+    #[msg("Math overflow")]
+    MathOverflow,
+
+    #[msg("Resolution time has not been reached")]
+    ResolutionTimeNotReached,
+
+    #[msg("Oracle account does not match the market's price feed")]
+    InvalidOracleAccount,
+
+    #[msg("Oracle price is unavailable")]
+    OraclePriceUnavailable,
+
+    #[msg("Market is not resolved")]
+    MarketNotResolved,
+
+    #[msg("Bet has not been revealed")]
+    NotRevealed,
+
+    #[msg("Winnings already claimed")]
+    AlreadyClaimed,
+
+    #[msg("Position is not on the winning side")]
+    NotAWinner,
+
+    #[msg("Market can no longer be cancelled")]
+    MarketNotCancellable,
+
+    #[msg("Market is not cancelled")]
+    MarketNotCancelled,
+
+    #[msg("Signer is not authorized for this action")]
+    Unauthorized,
+
+    #[msg("Basis points must not exceed 10000")]
+    InvalidBasisPoints,
+
+    #[msg("Unrevealed positions must be swept before claiming")]
+    UnrevealedPositionsPending,
+
+    #[msg("Treasury account does not match the config treasury")]
+    InvalidTreasury,
+
+    #[msg("Stake mint, token vault and token program must be provided together")]
+    InvalidStakeMint,
+
+    #[msg("Token account does not match the market's stake mint or owner")]
+    InvalidTokenAccount,
+
+    #[msg("Fee exceeds the maximum allowed")]
+    FeeTooHigh,
+
+    #[msg("No fees to withdraw")]
+    NoFeesToWithdraw,
+
+    #[msg("Invalid program config parameters")]
+    InvalidConfig,
+
+    #[msg("Outcome count must be between 2 and the maximum")]
+    InvalidOutcomeCount,
+
+    #[msg("Outcome does not exist on this market")]
+    InvalidOutcome,
+
+    #[msg("Instruction does not apply to this market type")]
+    MarketTypeMismatch,
+
+    #[msg("Price boundaries must be strictly ascending, one between each pair of buckets")]
+    InvalidPriceBoundaries,
+
+    #[msg("Scalar lower bound must be below the upper bound")]
+    InvalidScalarBounds,
+
+    #[msg("Oracle price exponent does not match the market")]
+    OracleExponentMismatch,
+
+    #[msg("Oracle price was not published close enough to the resolution time")]
+    OraclePriceStale,
+
+    #[msg("Oracle confidence interval is too wide")]
+    OracleConfidenceTooWide,
+
+    #[msg("Market resolution is not provisional")]
+    ResolutionNotProvisional,
+
+    #[msg("Dispute window has closed")]
+    DisputeWindowClosed,
+
+    #[msg("Dispute window is still open")]
+    DisputeWindowOpen,
+
+    #[msg("Resolution has been challenged and must be ruled on by the arbiter")]
+    DisputesPending,
+
+    #[msg("Resolution has not been challenged")]
+    NoDisputes,
+
+    #[msg("Oracle timeout has not been reached")]
+    OracleTimeoutNotReached,
+
+    #[msg("Invalid ed25519 signature instruction")]
+    InvalidSignatureInstruction,
+
+    #[msg("Oracle price update is not fully verified")]
+    OracleNotFullyVerified,
+
+    #[msg("Market IDs with the high bit set are reserved for series rounds")]
+    ReservedMarketId,
+
+    #[msg("Invalid series template")]
+    InvalidSeriesTemplate,
+
+    #[msg("Previous round account does not match the series")]
+    InvalidPreviousRound,
+
+    #[msg("Previous round has not been resolved or cancelled")]
+    PreviousRoundNotSettled,
+
+    #[msg("Position has not been claimed, refunded or swept")]
+    PositionNotSettled,
+
+    #[msg("Market still has open positions")]
+    PositionsOutstanding,
+
+    #[msg("Market cannot be closed until it is settled, its fees withdrawn and its disputes settled")]
+    MarketNotClosable,
+
+    #[msg("No ed25519 signature from the position owner authorizes this reveal")]
+    MissingRevealSignature,
+
+    #[msg("No revealed stake backed the winning outcome")]
+    NoWinningStake,
+
+    #[msg("Oracle price was published before the resolution time")]
+    OraclePriceBeforeResolution,
+
+    #[msg("Price update is not the first one published at or after the resolution time")]
+    OracleUpdateNotAtResolution,
+
+    #[msg("Arbitration deadline has passed")]
+    ArbitrationWindowClosed,
+
+    #[msg("Position counter stays until the user's positions are closed and the market stops taking bets")]
+    UserMarketNotClosable,
+}
+
+// ============================================================================
+// Helper Functions
+// ============================================================================
+
+/// Hash a bet commitment: SHA-256 over the domain tag, program ID, market, user,
+/// outcome index, little-endian stake and a 32-byte salt.
+/// Must match `hashCommitment` in `lib/blockchain-utils.ts`.
+fn hash_commitment(
+    market: &Pubkey,
+    user: &Pubkey,
+    outcome: u8,
+    stake_amount: u64,
+    salt: &[u8; 32],
+) -> [u8; 32] {
+    use anchor_lang::solana_program::hash::hashv;
+
+    hashv(&[
+        COMMITMENT_DOMAIN,
+        crate::ID.as_ref(),
+        market.as_ref(),
+        user.as_ref(),
+        &[outcome],
+        &stake_amount.to_le_bytes(),
+        salt,
+    ])
+    .to_bytes()
+}
+
+/// Check a reveal against the position's commitment and add its stake to the outcome pool
+fn reveal_position(
+    market: &mut Account<Market>,
+    position: &mut Account<UserPosition>,
+    outcome: u8,
+    salt: &[u8; 32],
+) -> Result<()> {
+    let clock = Clock::get()?;
+
+    // Validate market is locked
+    require!(
+        market.status == MarketStatus::Locked,
+        ErrorCode::MarketNotLocked
+    );
+
+    // Validate reveal window (opens at lock, closes at the reveal deadline)
+    require!(
+        clock.unix_timestamp >= market.lock_time && clock.unix_timestamp < market.reveal_deadline,
+        ErrorCode::RevealWindowClosed
+    );
+
+    // Validate not already revealed
+    require!(
+        !position.revealed,
+        ErrorCode::AlreadyRevealed
+    );
+
+    // Validate outcome exists on this market
+    require!(
+        outcome < market.outcome_count,
+        ErrorCode::InvalidOutcome
+    );
+
+    // Verify commitment hash
+    let computed_hash = hash_commitment(
+        &market.key(),
+        &position.user,
+        outcome,
+        position.stake_amount,
+        salt,
+    );
+    require!(
+        computed_hash == position.commitment_hash,
+        ErrorCode::InvalidCommitment
+    );
+
+    // Update position
+    position.outcome = Some(outcome);
+    position.revealed = true;
+    market.unrevealed_positions -= 1;
+
+    // Update market stakes
+    let outcome_stake = &mut market.outcome_stakes[outcome as usize];
+    *outcome_stake = outcome_stake
+        .checked_add(position.stake_amount)
+        .ok_or(ErrorCode::MathOverflow)?;
+    Ok(())
+}
+
+/// Message a position owner signs to let a relayer reveal for them:
+/// domain tag || program ID || position || outcome index || 32-byte salt
+fn reveal_message(position: &Pubkey, outcome: u8, salt: &[u8; 32]) -> Vec<u8> {
+    [
+        REVEAL_DOMAIN,
+        crate::ID.as_ref(),
+        position.as_ref(),
+        &[outcome],
+        salt,
+    ]
+    .concat()
+}
+
+/// Market ID of a series round: the first 8 bytes of SHA-256(series || round LE),
+/// with SERIES_MARKET_ID_FLAG set so it can never collide with an explicitly chosen ID
+fn series_market_id(series: &Pubkey, round: u64) -> u64 {
+    use anchor_lang::solana_program::hash::hashv;
+
+    let hash = hashv(&[b"series_round", series.as_ref(), &round.to_le_bytes()]).to_bytes();
+    u64::from_le_bytes(hash[..8].try_into().unwrap()) | SERIES_MARKET_ID_FLAG
+}
+
+/// Resolution time of the next series round: one interval after the previous round,
+/// skipping ahead whole intervals until the round still locks after `now`
+fn next_round_time(previous: i64, interval: i64, lock_buffer: i64, now: i64) -> Result<i64> {
+    require!(interval > 0, ErrorCode::InvalidSeriesTemplate);
+
+    // The round must satisfy next - lock_buffer > now
+    let earliest_lock = now.checked_add(lock_buffer).ok_or(ErrorCode::MathOverflow)?;
+    let next = previous.checked_add(interval).ok_or(ErrorCode::MathOverflow)?;
+    if next > earliest_lock {
+        return Ok(next);
+    }
+
+    let skipped = (earliest_lock - previous) / interval + 1;
+    skipped
+        .checked_mul(interval)
+        .and_then(|offset| previous.checked_add(offset))
+        .ok_or_else(|| error!(ErrorCode::MathOverflow))
+}
+
+/// Fund a new market's vault with its rent-exempt minimum, so stakes can always be paid out
+/// in full, plus the market's keeper tip pool
+fn fund_vault<'info>(
+    payer: &Signer<'info>,
+    vault: &SystemAccount<'info>,
+    system_program: &Program<'info, System>,
+    keeper_tip_pool: u64,
+) -> Result<()> {
+    let amount = Rent::get()?
+        .minimum_balance(0)
+        .checked_add(keeper_tip_pool)
+        .ok_or(ErrorCode::MathOverflow)?;
+    system_program::transfer(
+        CpiContext::new(
+            system_program.to_account_info(),
+            system_program::Transfer {
+                from: payer.to_account_info(),
+                to: vault.to_account_info(),
+            },
+        ),
+        amount,
+    )
+}
+
+/// Pay the caller of a successful crank instruction the market's keeper tip, while the
+/// market's tip pool lasts. Must run after every check so failed calls pay nothing.
+fn pay_keeper_tip<'info>(
+    market: &mut Account<'info, Market>,
+    vault: &SystemAccount<'info>,
+    keeper: &Signer<'info>,
+    system_program: &Program<'info, System>,
+) -> Result<()> {
+    let tip = market.keeper_tip.min(market.keeper_tip_pool);
+    if tip == 0 {
+        return Ok(());
+    }
+    market.keeper_tip_pool -= tip;
+
+    MarketCustody::new(market, vault, &None, &None, system_program)
+        .withdraw_lamports(&keeper.to_account_info(), tip)?;
+
+    emit!(KeeperTipPaid {
+        market: market.key(),
+        keeper: keeper.key(),
+        amount: tip,
+    });
+    Ok(())
+}
+
+/// Bucket containing a price, given ascending boundaries between buckets.
+/// Bucket i covers (boundaries[i - 1], boundaries[i]]; a price on a boundary falls in the
+/// lower bucket, matching the binary rule that Long needs a price strictly above the threshold.
+fn find_bucket(boundaries: &[i64], price: i64) -> u8 {
+    boundaries.partition_point(|boundary| *boundary < price) as u8
+}
+
+/// Payout for a winning stake: the stake back plus its pro-rata share of the losing pool.
+/// The share is rounded down, so any remainder (at most one lamport per winner) stays in the vault.
+fn calculate_payout(stake_amount: u64, winning_pool: u64, losing_pool: u64) -> Result<u64> {
+    require!(winning_pool > 0, ErrorCode::NoWinningStake);
+
+    let share = (stake_amount as u128)
+        .checked_mul(losing_pool as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        / winning_pool as u128;
+
+    let payout = (stake_amount as u128)
+        .checked_add(share)
+        .ok_or(ErrorCode::MathOverflow)?;
+
+    u64::try_from(payout).map_err(|_| error!(ErrorCode::MathOverflow))
+}
+
+/// Split a scalar market's pot between Long and Short by where the settlement price lands.
+/// The price is clamped to [lower_bound, upper_bound]; Long's allocation is rounded down and
+/// Short receives the remainder. If one side has no stake, the other side takes the whole pot.
+fn scalar_allocations(
+    total_pot: u64,
+    long_pool: u64,
+    short_pool: u64,
+    lower_bound: i64,
+    upper_bound: i64,
+    settlement_price: i64,
+) -> (u64, u64) {
+    if long_pool == 0 {
+        return (0, total_pot);
+    }
+    if short_pool == 0 {
+        return (total_pot, 0);
+    }
+
+    let clamped = settlement_price.clamp(lower_bound, upper_bound);
+    let range = (upper_bound as i128 - lower_bound as i128) as u128;
+    let progress = (clamped as i128 - lower_bound as i128) as u128;
+
+    // total_pot * progress fits in u128 and the quotient never exceeds total_pot
+    let long_allocation = (total_pot as u128 * progress / range) as u64;
+    (long_allocation, total_pot - long_allocation)
+}
+
+/// A stake's pro-rata share of a side's allocation, rounded down (dust stays in the vault)
+fn pro_rata_share(allocation: u64, stake_amount: u64, side_pool: u64) -> Result<u64> {
+    require!(side_pool > 0, ErrorCode::MathOverflow);
+
+    let share = allocation as u128 * stake_amount as u128 / side_pool as u128;
+    u64::try_from(share).map_err(|_| error!(ErrorCode::MathOverflow))
+}
+
+/// Portion of an amount given in basis points, rounded down
+fn apply_bps(amount: u64, bps: u16) -> Result<u64> {
+    let portion = (amount as u128)
+        .checked_mul(bps as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        / BPS_DENOMINATOR as u128;
+
+    u64::try_from(portion).map_err(|_| error!(ErrorCode::MathOverflow))
+}
+
+/// Minimum and maximum stake in base units for a stake asset with the given decimals,
+/// from bounds given in hundredths of a whole token
+fn stake_bounds(decimals: u8, min_hundredths: u64, max_hundredths: u64) -> Result<(u64, u64)> {
+    let scale = |hundredths: u64| {
+        10u128
+            .checked_pow(decimals as u32)
+            .and_then(|unit| unit.checked_mul(hundredths as u128))
+            .and_then(|amount| u64::try_from(amount / 100).ok())
+            .ok_or(ErrorCode::MathOverflow)
+    };
+
+    let min_stake = scale(min_hundredths)?.max(1);
+    let max_stake = scale(max_hundredths)?;
+
+    Ok((min_stake, max_stake))
+}
+
+/// Accounts holding a market's stakes: the lamport vault for SOL markets,
+/// or the token vault owned by the market PDA for SPL markets
+struct MarketCustody<'a, 'info> {
+    market: AccountInfo<'info>,
+    market_id: u64,
+    market_bump: u8,
+    vault_bump: u8,
+    stake_mint: Option<Pubkey>,
+    vault: &'a SystemAccount<'info>,
+    token_vault: Option<&'a Account<'info, TokenAccount>>,
+    token_program: Option<&'a Program<'info, Token>>,
+    system_program: &'a Program<'info, System>,
+}
+
+impl<'a, 'info> MarketCustody<'a, 'info> {
+    fn new(
+        market: &Account<'info, Market>,
+        vault: &'a SystemAccount<'info>,
+        token_vault: &'a Option<Account<'info, TokenAccount>>,
+        token_program: &'a Option<Program<'info, Token>>,
+        system_program: &'a Program<'info, System>,
+    ) -> Self {
+        Self {
+            market: market.to_account_info(),
+            market_id: market.market_id,
+            market_bump: market.bump,
+            vault_bump: market.vault_bump,
+            stake_mint: market.stake_mint,
+            vault,
+            token_vault: token_vault.as_ref(),
+            token_program: token_program.as_ref(),
+            system_program,
+        }
+    }
+
+    /// Token vault and program for SPL markets, checking `token_account` against the stake mint and `owner`
+    fn token_accounts<'t>(
+        &self,
+        stake_mint: Pubkey,
+        token_account: Option<&'t Account<'info, TokenAccount>>,
+        owner: &Pubkey,
+    ) -> Result<(
+        &'t Account<'info, TokenAccount>,
+        &'a Account<'info, TokenAccount>,
+        &'a Program<'info, Token>,
+    )> {
+        let token_account = token_account.ok_or(ErrorCode::InvalidTokenAccount)?;
+        require!(
+            token_account.mint == stake_mint && token_account.owner == *owner,
+            ErrorCode::InvalidTokenAccount
+        );
+
+        match (self.token_vault, self.token_program) {
+            (Some(token_vault), Some(token_program)) => Ok((token_account, token_vault, token_program)),
+            _ => err!(ErrorCode::InvalidStakeMint),
+        }
+    }
+
+    /// Move lamports from a signer into the SOL vault, whatever the market's stake mint
+    fn deposit_lamports(&self, from: &Signer<'info>, amount: u64) -> Result<()> {
+        system_program::transfer(
+            CpiContext::new(
+                self.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: from.to_account_info(),
+                    to: self.vault.to_account_info(),
+                },
+            ),
+            amount,
+        )
+    }
+
+    /// Pay lamports out of the SOL vault, signing with the vault PDA
+    fn withdraw_lamports(&self, to: &AccountInfo<'info>, amount: u64) -> Result<()> {
+        let signer_seeds: &[&[&[u8]]] =
+            &[&[b"vault", self.market.key.as_ref(), &[self.vault_bump]]];
+        system_program::transfer(
+            CpiContext::new_with_signer(
+                self.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: self.vault.to_account_info(),
+                    to: to.clone(),
+                },
+                signer_seeds,
+            ),
+            amount,
+        )
+    }
+
+    /// Close the empty SPL token vault, signing with the market PDA
+    fn close_token_vault(&self, destination: &AccountInfo<'info>) -> Result<()> {
+        let (Some(token_vault), Some(token_program)) = (self.token_vault, self.token_program) else {
+            return err!(ErrorCode::InvalidStakeMint);
+        };
+        let market_id = self.market_id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"market", market_id.as_ref(), &[self.market_bump]]];
+        token::close_account(CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            token::CloseAccount {
+                account: token_vault.to_account_info(),
+                destination: destination.clone(),
+                authority: self.market.clone(),
+            },
+            signer_seeds,
+        ))
+    }
+
+    /// Move a stake from a user into custody
+    fn deposit(
+        &self,
+        from: &Signer<'info>,
+        from_token_account: Option<&Account<'info, TokenAccount>>,
+        amount: u64,
+    ) -> Result<()> {
+        match self.stake_mint {
+            None => self.deposit_lamports(from, amount),
+            Some(stake_mint) => {
+                let (from_token_account, token_vault, token_program) =
+                    self.token_accounts(stake_mint, from_token_account, &from.key())?;
+                token::transfer(
+                    CpiContext::new(
+                        token_program.to_account_info(),
+                        token::Transfer {
+                            from: from_token_account.to_account_info(),
+                            to: token_vault.to_account_info(),
+                            authority: from.to_account_info(),
+                        },
+                    ),
+                    amount,
+                )
+            }
+        }
+    }
+
+    /// Pay out of custody, signing with the vault PDA (SOL) or market PDA (SPL)
+    fn withdraw(
+        &self,
+        to: &AccountInfo<'info>,
+        to_token_account: Option<&Account<'info, TokenAccount>>,
+        amount: u64,
+    ) -> Result<()> {
+        match self.stake_mint {
+            None => self.withdraw_lamports(to, amount),
+            Some(stake_mint) => {
+                let (to_token_account, token_vault, token_program) =
+                    self.token_accounts(stake_mint, to_token_account, to.key)?;
+                let market_id = self.market_id.to_le_bytes();
+                let signer_seeds: &[&[&[u8]]] =
+                    &[&[b"market", market_id.as_ref(), &[self.market_bump]]];
+                token::transfer(
+                    CpiContext::new_with_signer(
+                        token_program.to_account_info(),
+                        token::Transfer {
+                            from: token_vault.to_account_info(),
+                            to: to_token_account.to_account_info(),
+                            authority: self.market.clone(),
+                        },
+                        signer_seeds,
+                    ),
+                    amount,
+                )
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Oracle Adapters
+// ============================================================================
+
+/// Price reported by an oracle, worth `price * 10^expo` with confidence interval `conf`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct OraclePrice {
+    pub price: i64,
+    pub conf: u64,
+    pub expo: i32,
+    pub publish_time: i64,
+}
+
+/// Common interface over the oracle formats a market can settle against
+trait OracleAdapter {
+    fn read_price(&self) -> Result<OraclePrice>;
+}
+
+impl OracleSource {
+    /// Read the settlement price from this source. Account-based sources need the account
+    /// recorded on the market; signed attestations need the instructions sysvar.
+    fn read_price(
+        &self,
+        oracle_account: Option<&AccountInfo>,
+        instructions: Option<&AccountInfo>,
+        resolution_time: i64,
+    ) -> Result<OraclePrice> {
+        match self {
+            OracleSource::Pyth { price_account } => {
+                let account = expect_oracle_account(oracle_account, price_account)?;
+                let data = account.try_borrow_data()?;
+                PythAdapter { data: &data }.read_price()
+            }
+            // Price updates are posted to fresh accounts, so the feed ID is checked instead of the address
+            OracleSource::PythPull { feed_id } => {
+                let account = oracle_account.ok_or(ErrorCode::InvalidOracleAccount)?;
+                let data = account.try_borrow_data()?;
+                PythPullAdapter {
+                    data: &data,
+                    owner: account.owner,
+                    feed_id,
+                    resolution_time,
+                }
+                .read_price()
+            }
+            OracleSource::Switchboard { aggregator } => {
+                let account = expect_oracle_account(oracle_account, aggregator)?;
+                let data = account.try_borrow_data()?;
+                SwitchboardAdapter { data: &data }.read_price()
+            }
+            OracleSource::SignedAttestation { signer, feed_id } => {
+                let instructions = instructions.ok_or(ErrorCode::InvalidOracleAccount)?;
+                let messages = ed25519_signed_messages(instructions, signer)?;
+                AttestationAdapter {
+                    messages: &messages,
+                    feed_id,
+                    resolution_time,
+                }
+                .read_price()
+            }
+        }
+    }
+}
+
+/// The oracle account passed to the instruction, checked against the one on the market
+fn expect_oracle_account<'a, 'info>(
+    account: Option<&'a AccountInfo<'info>>,
+    expected: &Pubkey,
+) -> Result<&'a AccountInfo<'info>> {
+    let account = account.ok_or(ErrorCode::InvalidOracleAccount)?;
+    require_keys_eq!(*account.key, *expected, ErrorCode::InvalidOracleAccount);
+    Ok(account)
+}
+
+/// Bounds-checked little-endian reads from raw account or instruction data
+struct ByteReader<'a> {
+    data: &'a [u8],
+    error: ErrorCode, // Raised for any out-of-bounds read
+}
+
+impl<'a> ByteReader<'a> {
+    fn bytes<const N: usize>(&self, offset: usize) -> Result<[u8; N]> {
+        let bytes = self.slice(offset, N)?;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn slice(&self, offset: usize, len: usize) -> Result<&'a [u8]> {
+        Ok(self
+            .data
+            .get(offset..offset.checked_add(len).ok_or(self.error)?)
+            .ok_or(self.error)?)
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.bytes(offset)?))
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(offset)?))
+    }
+
+    fn i32(&self, offset: usize) -> Result<i32> {
+        Ok(i32::from_le_bytes(self.bytes(offset)?))
+    }
+
+    fn u64(&self, offset: usize) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes(offset)?))
+    }
+
+    fn i64(&self, offset: usize) -> Result<i64> {
+        Ok(i64::from_le_bytes(self.bytes(offset)?))
+    }
+
+    fn i128(&self, offset: usize) -> Result<i128> {
+        Ok(i128::from_le_bytes(self.bytes(offset)?))
+    }
+}
+
+const PYTH_MAGIC: u32 = 0xa1b2_c3d4;
+const PYTH_VERSION_2: u32 = 2;
+const PYTH_ACCOUNT_TYPE_PRICE: u32 = 3;
+const PYTH_STATUS_TRADING: u32 = 1;
+
+/// Aggregate price of a Pyth (v2) price account.
+/// Only the fixed header fields are read, so no Pyth SDK dependency is needed.
+struct PythAdapter<'a> {
+    data: &'a [u8],
+}
+
+impl OracleAdapter for PythAdapter<'_> {
+    fn read_price(&self) -> Result<OraclePrice> {
+        let reader = ByteReader {
+            data: self.data,
+            error: ErrorCode::InvalidOracleAccount,
+        };
+
+        require!(
+            reader.u32(0)? == PYTH_MAGIC
+                && reader.u32(4)? == PYTH_VERSION_2
+                && reader.u32(8)? == PYTH_ACCOUNT_TYPE_PRICE,
+            ErrorCode::InvalidOracleAccount
+        );
+
+        // Aggregate status must be Trading for the price to be meaningful
+        require!(
+            reader.u32(224)? == PYTH_STATUS_TRADING,
+            ErrorCode::OraclePriceUnavailable
+        );
+
+        Ok(OraclePrice {
+            price: reader.i64(208)?,
+            conf: reader.u64(216)?,
+            expo: reader.i32(20)?,
+            publish_time: reader.i64(96)?,
+        })
+    }
+}
+
+/// Pyth receiver program (rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ),
+/// owner of every posted `PriceUpdateV2` account
+pub const PYTH_RECEIVER_PROGRAM_ID: Pubkey = Pubkey::new_from_array([
+    12, 183, 250, 187, 82, 247, 166, 72, 187, 91, 49, 125, 154, 1, 139, 144, 87, 203, 2, 71,
+    116, 250, 254, 1, 230, 196, 223, 152, 204, 56, 88, 129,
+]);
+
+/// Anchor discriminator of Pyth `PriceUpdateV2`
+const PYTH_PRICE_UPDATE_DISCRIMINATOR: [u8; 8] = [34, 241, 35, 99, 157, 126, 244, 205];
+const PYTH_VERIFICATION_LEVEL: usize = 8 + 32;
+const PYTH_VERIFICATION_FULL: u8 = 1;
+
+/// Price message of a Pyth pull-oracle `PriceUpdateV2` account.
+/// The account must be owned by the Pyth receiver, fully verified (Wormhole guardian quorum),
+/// and carry the market's feed ID. Anyone can post any historical update, so only the first
+/// update at or after `resolution_time` is accepted: `prev_publish_time < resolution_time <= publish_time`.
+struct PythPullAdapter<'a> {
+    data: &'a [u8],
+    owner: &'a Pubkey,
+    feed_id: &'a [u8; 32],
+    resolution_time: i64,
+}
+
+impl OracleAdapter for PythPullAdapter<'_> {
+    fn read_price(&self) -> Result<OraclePrice> {
+        require_keys_eq!(
+            *self.owner,
+            PYTH_RECEIVER_PROGRAM_ID,
+            ErrorCode::InvalidOracleAccount
+        );
+
+        let reader = ByteReader {
+            data: self.data,
+            error: ErrorCode::InvalidOracleAccount,
+        };
+
+        require!(
+            reader.bytes::<8>(0)? == PYTH_PRICE_UPDATE_DISCRIMINATOR,
+            ErrorCode::InvalidOracleAccount
+        );
+
+        // Borsh enum: Partial { num_signatures } is two bytes, Full is one
+        require!(
+            reader.bytes::<1>(PYTH_VERIFICATION_LEVEL)?[0] == PYTH_VERIFICATION_FULL,
+            ErrorCode::OracleNotFullyVerified
+        );
+
+        // write_authority, then the one-byte Full verification level
+        let message = PYTH_VERIFICATION_LEVEL + 1;
+        require!(
+            reader.bytes::<32>(message)? == *self.feed_id,
+            ErrorCode::InvalidOracleAccount
+        );
+
+        let publish_time = reader.i64(message + 52)?;
+        let prev_publish_time = reader.i64(message + 60)?;
+        require!(
+            prev_publish_time < self.resolution_time && self.resolution_time <= publish_time,
+            ErrorCode::OracleUpdateNotAtResolution
+        );
+
+        Ok(OraclePrice {
+            price: reader.i64(message + 32)?,
+            conf: reader.u64(message + 40)?,
+            expo: reader.i32(message + 48)?,
+            publish_time,
+        })
+    }
+}
+
+/// Anchor discriminator of Switchboard v2 `AggregatorAccountData`
+const SWITCHBOARD_AGGREGATOR_DISCRIMINATOR: [u8; 8] = [217, 230, 65, 101, 201, 162, 27, 125];
+const SWITCHBOARD_MIN_ORACLE_RESULTS: usize = 236;
+const SWITCHBOARD_LATEST_ROUND: usize = 341;
+const SWITCHBOARD_ROUND_NUM_SUCCESS: usize = SWITCHBOARD_LATEST_ROUND;
+const SWITCHBOARD_ROUND_OPEN_TIMESTAMP: usize = SWITCHBOARD_LATEST_ROUND + 17;
+const SWITCHBOARD_ROUND_RESULT: usize = SWITCHBOARD_LATEST_ROUND + 25;
+const SWITCHBOARD_ROUND_STD_DEVIATION: usize = SWITCHBOARD_LATEST_ROUND + 45;
+
+/// Latest confirmed round of a Switchboard v2 aggregator.
+/// Results are decimals (`mantissa * 10^-scale`); the price keeps the result's scale as its
+/// exponent and the standard deviation, rescaled to match and rounded up, is the confidence.
+struct SwitchboardAdapter<'a> {
+    data: &'a [u8],
+}
+
+impl OracleAdapter for SwitchboardAdapter<'_> {
+    fn read_price(&self) -> Result<OraclePrice> {
+        let reader = ByteReader {
+            data: self.data,
+            error: ErrorCode::InvalidOracleAccount,
+        };
+
+        require!(
+            reader.bytes::<8>(0)? == SWITCHBOARD_AGGREGATOR_DISCRIMINATOR,
+            ErrorCode::InvalidOracleAccount
+        );
+
+        // The round only counts once enough oracles have responded
+        let num_success = reader.u32(SWITCHBOARD_ROUND_NUM_SUCCESS)?;
+        require!(
+            num_success > 0 && num_success >= reader.u32(SWITCHBOARD_MIN_ORACLE_RESULTS)?,
+            ErrorCode::OraclePriceUnavailable
+        );
+
+        let mantissa = reader.i128(SWITCHBOARD_ROUND_RESULT)?;
+        let scale = reader.u32(SWITCHBOARD_ROUND_RESULT + 16)?;
+        let std_mantissa = reader.i128(SWITCHBOARD_ROUND_STD_DEVIATION)?;
+        let std_scale = reader.u32(SWITCHBOARD_ROUND_STD_DEVIATION + 16)?;
+
+        let conf = rescale_round_up(std_mantissa.unsigned_abs(), std_scale, scale)
+            .and_then(|conf| u64::try_from(conf).ok())
+            .ok_or(ErrorCode::OraclePriceUnavailable)?;
+
+        Ok(OraclePrice {
+            price: i64::try_from(mantissa).map_err(|_| error!(ErrorCode::OraclePriceUnavailable))?,
+            conf,
+            expo: -i32::try_from(scale).map_err(|_| error!(ErrorCode::InvalidOracleAccount))?,
+            publish_time: reader.i64(SWITCHBOARD_ROUND_OPEN_TIMESTAMP)?,
+        })
+    }
+}
+
+/// Re-express `value * 10^-from_scale` with `to_scale` decimals, rounding up
+fn rescale_round_up(value: u128, from_scale: u32, to_scale: u32) -> Option<u128> {
+    if from_scale >= to_scale {
+        let divisor = 10u128.checked_pow(from_scale - to_scale)?;
+        Some(value.div_ceil(divisor))
+    } else {
+        value.checked_mul(10u128.checked_pow(to_scale - from_scale)?)
+    }
+}
+
+/// Domain-separation tag prefixed to every signed price attestation
+pub const ATTESTATION_DOMAIN: &[u8] = b"darkbet:price:v1";
+
+/// domain || feed_id (32) || price (i64) || conf (u64) || expo (i32) || publish_time (i64)
+const ATTESTATION_LEN: usize = ATTESTATION_DOMAIN.len() + 32 + 8 + 8 + 4 + 8;
+
+/// Price attestation signed by the market's attestation key, carried in an ed25519
+/// program instruction earlier in the transaction. Only an attestation for the market's
+/// feed ID published exactly at `resolution_time` settles the market, so the caller cannot
+/// choose among the signer's other prices; all values are little-endian.
+struct AttestationAdapter<'a> {
+    messages: &'a [Vec<u8>],
+    feed_id: &'a [u8; 32],
+    resolution_time: i64,
+}
+
+impl OracleAdapter for AttestationAdapter<'_> {
+    fn read_price(&self) -> Result<OraclePrice> {
+        let fields = ATTESTATION_DOMAIN.len() + 32;
+        let publish_time_at = fields + 20;
+        let message = self
+            .messages
+            .iter()
+            .find(|message| {
+                message.len() == ATTESTATION_LEN
+                    && message.starts_with(ATTESTATION_DOMAIN)
+                    && message[ATTESTATION_DOMAIN.len()..][..32] == self.feed_id[..]
+                    && message[publish_time_at..] == self.resolution_time.to_le_bytes()
+            })
+            .ok_or(ErrorCode::OraclePriceUnavailable)?;
+
+        let reader = ByteReader {
+            data: message,
+            error: ErrorCode::InvalidOracleAccount,
+        };
+
+        Ok(OraclePrice {
+            price: reader.i64(fields)?,
+            conf: reader.u64(fields + 8)?,
+            expo: reader.i32(fields + 16)?,
+            publish_time: reader.i64(publish_time_at)?,
+        })
+    }
+}
+
+/// Size of one signature's offsets record in an ed25519 program instruction
+const ED25519_OFFSETS_SIZE: usize = 14;
+
+/// Messages signed by `signer` in ed25519 program instructions that precede the current
+/// instruction. The runtime has already verified those signatures, or the transaction would fail.
+fn ed25519_signed_messages(instructions: &AccountInfo, signer: &Pubkey) -> Result<Vec<Vec<u8>>> {
+    let current_index = sysvar_instructions::load_current_index_checked(instructions)?;
+
+    let mut messages = Vec::new();
+    for index in 0..current_index {
+        let instruction = sysvar_instructions::load_instruction_at_checked(index as usize, instructions)?;
+        if instruction.program_id != ed25519_program::ID {
+            continue;
+        }
+        for (pubkey, message) in parse_ed25519_instruction(&instruction.data)? {
+            if pubkey == *signer {
+                messages.push(message.to_vec());
+            }
+        }
+    }
+    Ok(messages)
 }
 
-#[account]
-#[derive(InitSpace)]
-pub struct UserPosition {
-    pub user: Pubkey,                // 32
-    pub market: Pubkey,              // 32
-    pub stake_amount: u64,           // 8
-    pub commitment_hash: [u8; 32],   // 32
-    pub direction: Option<Direction>, // 1 + 1
-    pub revealed: bool,              // 1
-    pub claimed: bool,               // 1
-    pub committed_at: i64,           // 8
-    pub bump: u8,                    // 1
-}
+/// Public keys and messages of every signature in an ed25519 program instruction.
+/// Only signatures whose key, signature and message all live in that same instruction are
+/// accepted, so the verified bytes are exactly the ones returned.
+fn parse_ed25519_instruction(data: &[u8]) -> Result<Vec<(Pubkey, &[u8])>> {
+    let reader = ByteReader {
+        data,
+        error: ErrorCode::InvalidSignatureInstruction,
+    };
+    let count = reader.bytes::<1>(0)?[0] as usize;
+
+    (0..count)
+        .map(|i| {
+            // Offsets records start after the count and a padding byte
+            let record = 2 + i * ED25519_OFFSETS_SIZE;
+            let signature_ix = reader.u16(record + 2)?;
+            let pubkey_offset = reader.u16(record + 4)? as usize;
+            let pubkey_ix = reader.u16(record + 6)?;
+            let message_offset = reader.u16(record + 8)? as usize;
+            let message_size = reader.u16(record + 10)? as usize;
+            let message_ix = reader.u16(record + 12)?;
+
+            require!(
+                signature_ix == u16::MAX && pubkey_ix == u16::MAX && message_ix == u16::MAX,
+                ErrorCode::InvalidSignatureInstruction
+            );
+
+            let pubkey = Pubkey::new_from_array(reader.bytes::<32>(pubkey_offset)?);
+            Ok((pubkey, reader.slice(message_offset, message_size)?))
+        })
+        .collect()
+}
+
+/// Check an oracle price against a market's settlement requirements: the exponent must match,
+/// the price must be published at or after `resolution_time` and at most `max_staleness` seconds
+/// later, and the confidence interval must be at most `max_confidence_bps` of `reference_price`.
+fn check_oracle_price(
+    price: &OraclePrice,
+    expected_expo: i32,
+    resolution_time: i64,
+    max_staleness: i64,
+    max_confidence_bps: u16,
+    reference_price: i64,
+) -> Result<()> {
+    require!(price.expo == expected_expo, ErrorCode::OracleExponentMismatch);
+
+    // A price from before resolution_time says nothing about the price at resolution
+    require!(
+        price.publish_time >= resolution_time,
+        ErrorCode::OraclePriceBeforeResolution
+    );
+    let delay = price.publish_time as i128 - resolution_time as i128;
+    require!(delay <= max_staleness as i128, ErrorCode::OraclePriceStale);
+
+    // conf / |reference| <= max_confidence_bps / 10_000, cross-multiplied in u128
+    require!(
+        price.conf as u128 * BPS_DENOMINATOR as u128
+            <= reference_price.unsigned_abs() as u128 * max_confidence_bps as u128,
+        ErrorCode::OracleConfidenceTooWide
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payout_splits_losing_pool_pro_rata() {
+        // 3 SOL winning pool, 1 SOL losing pool
+        assert_eq!(calculate_payout(1_000_000_000, 3_000_000_000, 1_000_000_000).unwrap(), 1_333_333_333);
+        assert_eq!(calculate_payout(2_000_000_000, 3_000_000_000, 1_000_000_000).unwrap(), 2_666_666_666);
+    }
+
+    #[test]
+    fn payout_rounds_down_and_leaves_dust_in_vault() {
+        let stakes = [10_000_000u64, 20_000_000, 30_000_001];
+        let winning_pool: u64 = stakes.iter().sum();
+        let losing_pool = 77_777_777u64;
+
+        let paid: u64 = stakes
+            .iter()
+            .map(|stake| calculate_payout(*stake, winning_pool, losing_pool).unwrap())
+            .sum();
+        let dust = winning_pool + losing_pool - paid;
+
+        // floor(stake * 77_777_777 / 60_000_001) added to each stake
+        assert_eq!(calculate_payout(stakes[0], winning_pool, losing_pool).unwrap(), 22_962_962);
+        assert_eq!(calculate_payout(stakes[1], winning_pool, losing_pool).unwrap(), 45_925_925);
+        assert_eq!(calculate_payout(stakes[2], winning_pool, losing_pool).unwrap(), 68_888_890);
+        assert_eq!(dust, 1);
+    }
+
+    #[test]
+    fn payout_returns_stake_when_losing_pool_is_empty() {
+        assert_eq!(calculate_payout(50_000_000, 50_000_000, 0).unwrap(), 50_000_000);
+    }
+
+    #[test]
+    fn payout_takes_whole_losing_pool_for_sole_winner() {
+        assert_eq!(calculate_payout(10_000_000, 10_000_000, 100_000_000_000).unwrap(), 100_010_000_000);
+    }
+
+    #[test]
+    fn payout_handles_large_pools_without_overflow() {
+        assert_eq!(calculate_payout(u64::MAX / 2, u64::MAX / 2, u64::MAX / 2).unwrap(), u64::MAX - 1);
+        assert!(calculate_payout(u64::MAX, u64::MAX, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn payout_rejects_empty_winning_pool() {
+        assert_eq!(
+            calculate_payout(0, 0, 1_000).unwrap_err(),
+            error!(ErrorCode::NoWinningStake)
+        );
+    }
+
+    #[test]
+    fn market_params_require_a_betting_window_before_lock() {
+        let params = |resolution_time| MarketParams {
+            asset_type: AssetType::SOL,
+            resolution_time,
+            oracle_source: OracleSource::Pyth { price_account: Pubkey::new_unique() },
+            threshold_price: 100,
+            unrevealed_policy: UnrevealedPolicy::ForfeitToWinners,
+            market_type: MarketType::Binary,
+            price_boundaries: vec![],
+            price_expo: -8,
+        };
+
+        // With a 300s lock buffer the market must resolve more than 300s from now
+        assert!(params(1_301).validate(1_000, 300).is_ok());
+        for resolution_time in [1_300, 1_100, i64::MIN] {
+            assert_eq!(
+                params(resolution_time).validate(1_000, 300).unwrap_err(),
+                error!(ErrorCode::InvalidResolutionTime)
+            );
+        }
+    }
+
+    fn test_market() -> Market {
+        Market {
+            authority: Pubkey::new_unique(),
+            market_id: 1,
+            asset_type: AssetType::SOL,
+            resolution_time: 1_700_000_000,
+            oracle_source: OracleSource::Pyth { price_account: Pubkey::new_unique() },
+            threshold_price: 100,
+            status: MarketStatus::Resolved,
+            settlement_price: Some(90),
+            created_at: 1_699_990_000,
+            bump: 255,
+            vault_bump: 255,
+            unrevealed_policy: UnrevealedPolicy::ForfeitToWinners,
+            unrevealed_positions: 0,
+            forfeited_stake: 0,
+            stake_mint: None,
+            token_vault_bump: 0,
+            min_stake: 10_000_000,
+            max_stake: 100_000_000_000,
+            fee_bps: 200,
+            fees_accrued: 0,
+            lock_time: 1_699_999_700,
+            reveal_deadline: 1_699_999_850,
+            early_exit_fee_bps: 0,
+            market_type: MarketType::Binary,
+            outcome_count: 2,
+            outcome_stakes: [0; MAX_OUTCOMES],
+            winning_outcome: Some(Direction::Short.outcome()),
+            price_boundaries: [0; MAX_OUTCOMES - 1],
+            price_expo: -8,
+            max_price_staleness: 60,
+            max_confidence_bps: 100,
+            dispute_window: 3_600,
+            dispute_bond: 100_000_000,
+            arbitration_window: 86_400,
+            dispute_deadline: 1_700_003_600,
+            dispute_count: 0,
+            resolution_overridden: false,
+            arbitration_expired: false,
+            manually_resolved: false,
+            keeper_tip: 0,
+            keeper_tip_pool: 0,
+            rent_payer: Pubkey::new_unique(),
+            open_positions: 0,
+            series: None,
+            resolution_grace_period: 86_400,
+            oracle_timeout: 3_600,
+        }
+    }
+
+    #[test]
+    fn claims_on_market_nobody_won_refund_revealed_stakes() {
+        // Short won, but every revealed stake backed Long
+        let mut market = test_market();
+        let long = Direction::Long.outcome();
+        let stakes = [30_000_000u64, 70_000_000];
+        market.outcome_stakes[long as usize] = stakes.iter().sum();
+
+        assert!(market.has_no_winners());
+        assert!(!market.is_losing_outcome(long));
+        for stake in stakes {
+            assert_eq!(market.claim(long, stake).unwrap(), (stake, 0));
+        }
+        assert_eq!(market.fees_accrued, 0);
+    }
+
+    #[test]
+    fn user_market_closes_only_once_the_market_stops_taking_bets() {
+        let mut market = test_market();
+        let mut user_market = UserMarket {
+            user: Pubkey::new_unique(),
+            market: Pubkey::new_unique(),
+            position_count: 3,
+            open_positions: 1,
+            bump: 255,
+        };
+        assert!(!user_market.is_closable(&market));
+
+        // Withdrawing every commitment on an open market keeps the counter, so indices keep rising
+        user_market.open_positions = 0;
+        market.status = MarketStatus::Open;
+        assert!(!user_market.is_closable(&market));
+
+        for status in [MarketStatus::Locked, MarketStatus::Resolved, MarketStatus::Cancelled] {
+            market.status = status;
+            assert!(user_market.is_closable(&market));
+        }
+    }
+
+    /// Stake left in the vault after every revealed position claims
+    fn leftover_after_claims(market: &mut Market, claims: &[(u8, u64)]) -> u64 {
+        let deposited = market.total_revealed_stake().unwrap() + market.forfeited_stake;
+        let paid: u64 = claims
+            .iter()
+            .map(|(outcome, stake)| {
+                let (payout, fee) = market.claim(*outcome, *stake).unwrap();
+                payout + fee
+            })
+            .sum();
+        deposited - paid
+    }
+
+    #[test]
+    fn closing_vault_separates_rounding_dust_from_forfeits() {
+        let (long, short) = (Direction::Long.outcome(), Direction::Short.outcome());
+
+        // Winners share the losing pool and the forfeits; only rounding dust is left
+        let mut market = test_market();
+        market.outcome_stakes[long as usize] = 77_777_777;
+        market.outcome_stakes[short as usize] = 60_000_001;
+        market.forfeited_stake = 5_000_000;
+        let claims = [(short, 10_000_000), (short, 20_000_000), (short, 30_000_001)];
+        let leftover = leftover_after_claims(&mut market, &claims);
+        // Shares of the 82_777_777 pot floor to 13_796_295 + 27_592_591 + 41_388_889
+        assert_eq!(leftover, 2);
+        assert_eq!(market.split_leftover_stake(leftover), (2, 0));
+
+        // Nobody backed the winner: revealed stakes are refunded and the forfeits are stranded
+        let mut market = test_market();
+        market.outcome_stakes[long as usize] = 100_000_000;
+        market.forfeited_stake = 5_000_000;
+        let claims = [(long, 40_000_000), (long, 60_000_000)];
+        let leftover = leftover_after_claims(&mut market, &claims);
+        assert_eq!(leftover, 5_000_000);
+        assert_eq!(market.split_leftover_stake(leftover), (0, 5_000_000));
 
-// ============================================================================
-// Enums
-// ============================================================================
+        // Cancelled markets refund every stake and forfeit nothing
+        market.status = MarketStatus::Cancelled;
+        assert_eq!(market.split_leftover_stake(3), (3, 0));
+    }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace)]
-pub enum MarketStatus {
-    Open,
-    Locked,
-    Resolved,
-    Cancelled,
-}
+    #[test]
+    fn claims_pay_winners_and_reject_losers() {
+        let mut market = test_market();
+        let (long, short) = (Direction::Long.outcome(), Direction::Short.outcome());
+        market.outcome_stakes[long as usize] = 100_000_000;
+        market.outcome_stakes[short as usize] = 50_000_000;
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace, Debug)]
-pub enum Direction {
-    Long,  // Betting price will be ABOVE threshold
-    Short, // Betting price will be BELOW threshold
-}
+        assert!(!market.has_no_winners());
+        assert!(market.is_losing_outcome(long));
+        assert_eq!(
+            market.claim(long, 100_000_000).unwrap_err(),
+            error!(ErrorCode::NotAWinner)
+        );
+        // 2% of the 100_000_000 won
+        assert_eq!(market.claim(short, 50_000_000).unwrap(), (148_000_000, 2_000_000));
+        assert_eq!(market.fees_accrued, 2_000_000);
+    }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace)]
-pub enum AssetType {
-    BTC,
-    ETH,
-    SOL,
-    BNB,
-}
+    #[test]
+    fn vault_balances_through_a_market_lifecycle() {
+        let (long, short) = (Direction::Long.outcome(), Direction::Short.outcome());
+        let mut market = test_market();
+        market.status = MarketStatus::Open;
+        market.early_exit_fee_bps = 100;
+        market.winning_outcome = None;
+        let now = market.lock_time - 100;
+        let mut vault = 0u64;
 
-// ============================================================================
-// Events
-// ============================================================================
+        // Four commitments; the second is topped up and the third withdrawn before lock
+        for stake in [50_000_000, 30_000_000, 20_000_000, 10_000_000] {
+            market.commit(stake, now).unwrap();
+            vault += stake;
+        }
+        assert_eq!(market.top_up(30_000_000, 10_000_000, now).unwrap(), 40_000_000);
+        vault += 10_000_000;
+        let (refund, fee) = market.withdraw_commitment(20_000_000, now).unwrap();
+        assert_eq!((refund, fee), (19_800_000, 200_000));
+        vault -= refund;
+        assert_eq!((market.unrevealed_positions, market.open_positions), (3, 3));
 
-#[event]
-pub struct MarketCreated {
-    pub market_id: u64,
-    pub authority: Pubkey,
-    pub asset_type: AssetType,
-    pub resolution_time: i64,
-    pub threshold_price: i64,
-}
+        // Long 50_000_000 and Short 40_000_000 are revealed; the 10_000_000 commitment is not
+        market.outcome_stakes[long as usize] = 50_000_000;
+        market.outcome_stakes[short as usize] = 40_000_000;
+        market.unrevealed_positions = 1;
+        market.status = MarketStatus::Resolved;
+        market.winning_outcome = Some(short);
+        assert_eq!(
+            market.claim(short, 40_000_000).unwrap_err(),
+            error!(ErrorCode::UnrevealedPositionsPending)
+        );
 
-#[event]
-pub struct BetCommitted {
-    pub user: Pubkey,
-    pub market: Pubkey,
-    pub stake_amount: u64,
-    pub commitment_hash: [u8; 32],
-}
+        let swept = market.sweep(10_000_000).unwrap();
+        assert_eq!(swept.refunded + swept.to_treasury, 0);
+        assert_eq!(market.forfeited_stake, 10_000_000);
 
-#[event]
-pub struct BetRevealed {
-    pub user: Pubkey,
-    pub market: Pubkey,
-    pub direction: Direction,
-    pub stake_amount: u64,
-}
+        // The winner takes the losing pool and the forfeit, less 2% of the 60_000_000 won
+        let (payout, fee) = market.claim(short, 40_000_000).unwrap();
+        assert_eq!((payout, fee), (98_800_000, 1_200_000));
+        vault -= payout;
 
-#[event]
-pub struct MarketLocked {
-    pub market_id: u64,
-    pub lock_time: i64,
-}
+        // Only the accrued fees remain, and withdrawing them empties the stake
+        assert_eq!(market.fees_accrued, 1_400_000);
+        assert_eq!(vault, market.fees_accrued);
+        vault -= market.fees_accrued;
 
-// ============================================================================
-// Error Codes
-// ============================================================================
+        // Closing returns rent and the unused tip pool; no stake is left over
+        market.keeper_tip_pool = 5_000;
+        let rent = 890_880;
+        assert_eq!(
+            market.split_sol_vault(rent + market.keeper_tip_pool + vault, rent),
+            (rent + 5_000, 0)
+        );
+    }
 
-#[error_code]
-pub enum ErrorCode {
-    #[msg("Resolution time must be in the future")]
-    InvalidResolutionTime,
+    #[test]
+    fn commitments_respect_lock_time_and_stake_bounds() {
+        let mut market = test_market();
+        market.status = MarketStatus::Open;
+        let now = market.lock_time - 1;
 
-    #[msg("Market is not open for betting")]
-    MarketNotOpen,
+        assert_eq!(
+            market.commit(market.min_stake - 1, now).unwrap_err(),
+            error!(ErrorCode::StakeTooLow)
+        );
+        assert_eq!(
+            market.commit(market.max_stake + 1, now).unwrap_err(),
+            error!(ErrorCode::StakeTooHigh)
+        );
+        assert_eq!(
+            market.commit(market.min_stake, market.lock_time).unwrap_err(),
+            error!(ErrorCode::MarketLocked)
+        );
+        assert_eq!(
+            market.top_up(market.min_stake, 0, now).unwrap_err(),
+            error!(ErrorCode::StakeTooLow)
+        );
+        assert_eq!(
+            market.top_up(market.max_stake, 1, now).unwrap_err(),
+            error!(ErrorCode::StakeTooHigh)
+        );
+        assert_eq!(
+            market.withdraw_commitment(market.min_stake, market.lock_time).unwrap_err(),
+            error!(ErrorCode::MarketLocked)
+        );
+        assert_eq!(market.unrevealed_positions, 0);
 
-    #[msg("Market is locked, no new bets allowed")]
-    MarketLocked,
+        market.status = MarketStatus::Locked;
+        assert_eq!(
+            market.commit(market.min_stake, now).unwrap_err(),
+            error!(ErrorCode::MarketNotOpen)
+        );
+    }
 
-    #[msg("Stake amount is too low (minimum 0.01 SOL)")]
-    StakeTooLow,
+    #[test]
+    fn refunds_require_a_cancelled_market() {
+        let mut market = test_market();
+        assert_eq!(
+            market.refund(10_000_000).unwrap_err(),
+            error!(ErrorCode::MarketNotCancelled)
+        );
 
-    #[msg("Stake amount is too high (maximum 100 SOL)")]
-    StakeTooHigh,
+        market.status = MarketStatus::Cancelled;
+        assert_eq!(market.refund(10_000_000).unwrap(), 10_000_000);
+    }
 
-    #[msg("Market is not locked yet")]
-    MarketNotLocked,
+    #[test]
+    fn sweeping_follows_the_unrevealed_policy() {
+        let cases = [
+            (UnrevealedPolicy::ForfeitToWinners, (0, 10_000_000, 0), 10_000_000),
+            (UnrevealedPolicy::ForfeitToTreasury, (0, 10_000_000, 10_000_000), 0),
+            (
+                UnrevealedPolicy::RefundWithPenalty { penalty_bps: 1_000 },
+                (9_000_000, 1_000_000, 0),
+                1_000_000,
+            ),
+        ];
 
-    #[msg("Reveal window is closed")]
-    RevealWindowClosed,
+        for (policy, (refunded, forfeited, to_treasury), kept_for_winners) in cases {
+            let mut market = test_market();
+            market.status = MarketStatus::Provisional;
+            market.unrevealed_policy = policy;
+            market.unrevealed_positions = 2;
 
-    #[msg("Bet already revealed")]
-    AlreadyRevealed,
+            assert_eq!(
+                market.sweep(10_000_000).unwrap(),
+                SweptStake { refunded, forfeited, to_treasury }
+            );
+            assert_eq!(market.forfeited_stake, kept_for_winners);
+            assert_eq!(market.unrevealed_positions, 1);
+        }
 
-    #[msg("Invalid commitment hash")]
-    InvalidCommitment,
+        // Stakes can only be swept once the reveal window has closed
+        let mut market = test_market();
+        market.status = MarketStatus::Locked;
+        assert_eq!(
+            market.sweep(10_000_000).unwrap_err(),
+            error!(ErrorCode::MarketNotResolved)
+        );
+    }
 
-    #[msg("Lock time has not been reached")]
-    LockTimeNotReached,
+    #[test]
+    fn disputes_run_through_arbitration_or_time_out() {
+        let provisional = || {
+            let mut market = test_market();
+            market.status = MarketStatus::Provisional;
+            market
+        };
+        let deadline = test_market().dispute_deadline;
+        let arbitration_deadline = test_market().arbitration_deadline().unwrap();
 
-    #[msg("Math overflow")]
-    MathOverflow,
-}
+        // Confirmed: the challenge failed and its bond is slashed
+        let mut market = provisional();
+        market.challenge(deadline - 1).unwrap();
+        assert_eq!(
+            market.challenge(deadline).unwrap_err(),
+            error!(ErrorCode::DisputeWindowClosed)
+        );
+        assert_eq!(
+            market.arbitrate(ArbiterRuling::Confirm, deadline - 1).unwrap_err(),
+            error!(ErrorCode::DisputeWindowOpen)
+        );
+        assert_eq!(
+            market.finalize(deadline).unwrap_err(),
+            error!(ErrorCode::DisputesPending)
+        );
+        market.arbitrate(ArbiterRuling::Confirm, deadline).unwrap();
+        assert!(market.status == MarketStatus::Resolved);
+        assert!(!market.settle_dispute().unwrap());
+        assert_eq!(
+            market.settle_dispute().unwrap_err(),
+            error!(ErrorCode::NoDisputes)
+        );
 
-// ============================================================================
-// Helper Functions
-// ============================================================================
+        // Overridden: the market is re-settled and the bond returned
+        let mut market = provisional();
+        market.challenge(deadline - 1).unwrap();
+        market
+            .arbitrate(ArbiterRuling::OverridePrice { settlement_price: 110 }, deadline)
+            .unwrap();
+        assert_eq!(market.winning_outcome, Some(Direction::Long.outcome()));
+        assert_eq!(market.settlement_price, Some(110));
+        assert!(market.settle_dispute().unwrap());
 
-/// Hash the commitment (simplified version - in production use Blake3)
-fn hash_commitment(direction: &Direction, nonce: &str, timestamp: i64) -> [u8; 32] {
-    use anchor_lang::solana_program::hash::hash;
-    
-    let mut data = Vec::new();
-    data.extend_from_slice(&[match direction {
-        Direction::Long => 1u8,
-        Direction::Short => 2u8,
-    }]);
-    data.extend_from_slice(nonce.as_bytes());
-    data.extend_from_slice(&timestamp.to_le_bytes());
-    
-    hash(&data).to_bytes()
-}
+        // Timed out: the resolution stands and the bond is returned
+        let mut market = provisional();
+        market.challenge(deadline - 1).unwrap();
+        assert_eq!(
+            market.arbitrate(ArbiterRuling::Confirm, arbitration_deadline).unwrap_err(),
+            error!(ErrorCode::ArbitrationWindowClosed)
+        );
+        market.finalize(arbitration_deadline).unwrap();
+        assert!(market.arbitration_expired);
+        assert_eq!(market.winning_outcome, Some(Direction::Short.outcome()));
+        assert!(market.settle_dispute().unwrap());
+
+        // Unchallenged: finalizes as soon as the dispute window closes
+        let mut market = provisional();
+        assert_eq!(
+            market.arbitrate(ArbiterRuling::Confirm, deadline).unwrap_err(),
+            error!(ErrorCode::NoDisputes)
+        );
+        assert_eq!(
+            market.finalize(deadline - 1).unwrap_err(),
+            error!(ErrorCode::DisputeWindowOpen)
+        );
+        market.finalize(deadline).unwrap();
+        assert!(!market.arbitration_expired);
+        assert_eq!(
+            market.challenge(deadline - 1).unwrap_err(),
+            error!(ErrorCode::ResolutionNotProvisional)
+        );
+    }
+
+    #[test]
+    fn closing_sol_vault_reserves_rent_and_tip_pool() {
+        let mut market = test_market();
+        market.keeper_tip_pool = 15_000;
+        let rent = 890_880;
+
+        // Stake left beyond the rent and the unused tips goes to the treasury
+        assert_eq!(market.split_sol_vault(rent + 15_000 + 42, rent), (rent + 15_000, 42));
+        // A vault short of its reserve returns everything to the rent payer
+        assert_eq!(market.split_sol_vault(rent, rent), (rent, 0));
+    }
 
+    #[test]
+    fn commitment_binds_every_field() {
+        let market = Pubkey::new_unique();
+        let user = Pubkey::new_unique();
+        let salt = [7u8; 32];
+        let base = hash_commitment(&market, &user, 0, 10_000_000, &salt);
 
+        assert_eq!(base, hash_commitment(&market, &user, 0, 10_000_000, &salt));
+        assert_ne!(base, hash_commitment(&Pubkey::new_unique(), &user, 0, 10_000_000, &salt));
+        assert_ne!(base, hash_commitment(&market, &Pubkey::new_unique(), 0, 10_000_000, &salt));
+        assert_ne!(base, hash_commitment(&market, &user, 1, 10_000_000, &salt));
+        assert_ne!(base, hash_commitment(&market, &user, 0, 10_000_001, &salt));
+        assert_ne!(base, hash_commitment(&market, &user, 0, 10_000_000, &[8u8; 32]));
+    }
+
+    #[test]
+    fn bucket_search_places_prices_in_bands() {
+        let boundaries = [100, 200, 300];
+
+        assert_eq!(find_bucket(&boundaries, i64::MIN), 0);
+        assert_eq!(find_bucket(&boundaries, 99), 0);
+        assert_eq!(find_bucket(&boundaries, 150), 1);
+        assert_eq!(find_bucket(&boundaries, 250), 2);
+        assert_eq!(find_bucket(&boundaries, 301), 3);
+        assert_eq!(find_bucket(&boundaries, i64::MAX), 3);
+    }
+
+    #[test]
+    fn bucket_boundaries_belong_to_the_lower_bucket() {
+        let boundaries = [100, 200, 300];
+
+        assert_eq!(find_bucket(&boundaries, 100), 0);
+        assert_eq!(find_bucket(&boundaries, 101), 1);
+        assert_eq!(find_bucket(&boundaries, 200), 1);
+        assert_eq!(find_bucket(&boundaries, 300), 2);
+        // A single boundary splits prices the same way as a binary threshold
+        assert_eq!(find_bucket(&[100], 100), 0);
+        assert_eq!(find_bucket(&[100], 101), 1);
+    }
+
+    #[test]
+    fn scalar_splits_pot_linearly_across_range() {
+        // Midpoint splits the pot evenly regardless of pool sizes
+        assert_eq!(scalar_allocations(1_000, 300, 700, 100, 200, 150), (500, 500));
+        assert_eq!(scalar_allocations(1_000, 300, 700, 100, 200, 175), (750, 250));
+        assert_eq!(scalar_allocations(1_000, 300, 700, -100, 100, 0), (500, 500));
+    }
+
+    #[test]
+    fn scalar_clamps_price_to_bounds() {
+        assert_eq!(scalar_allocations(1_000, 300, 700, 100, 200, 100), (0, 1_000));
+        assert_eq!(scalar_allocations(1_000, 300, 700, 100, 200, i64::MIN), (0, 1_000));
+        assert_eq!(scalar_allocations(1_000, 300, 700, 100, 200, 200), (1_000, 0));
+        assert_eq!(scalar_allocations(1_000, 300, 700, 100, 200, i64::MAX), (1_000, 0));
+    }
+
+    #[test]
+    fn scalar_rounds_long_down_and_gives_remainder_to_short() {
+        // 1/3 of 1_000 is 333.33..., Long gets 333 and Short the remaining 667
+        assert_eq!(scalar_allocations(1_000, 1, 1, 0, 3, 1), (333, 667));
+        // Full i64 range does not overflow
+        assert_eq!(scalar_allocations(u64::MAX, 1, 1, i64::MIN, i64::MAX, i64::MAX), (u64::MAX, 0));
+    }
+
+    #[test]
+    fn scalar_gives_whole_pot_to_only_staked_side() {
+        assert_eq!(scalar_allocations(1_000, 0, 1_000, 100, 200, 200), (0, 1_000));
+        assert_eq!(scalar_allocations(1_000, 1_000, 0, 100, 200, 100), (1_000, 0));
+    }
+
+    #[test]
+    fn pro_rata_shares_never_exceed_allocation() {
+        let stakes = [10_000_000u64, 20_000_000, 30_000_001];
+        let side_pool: u64 = stakes.iter().sum();
+        let allocation = 45_678_901u64;
+
+        let paid: u64 = stakes
+            .iter()
+            .map(|stake| pro_rata_share(allocation, *stake, side_pool).unwrap())
+            .sum();
+
+        assert!(paid <= allocation);
+        assert!(allocation - paid < stakes.len() as u64);
+    }
+
+    fn oracle_price(price: i64, conf: u64, publish_time: i64) -> OraclePrice {
+        OraclePrice { price, conf, expo: -8, publish_time }
+    }
+
+    #[test]
+    fn oracle_price_within_limits_is_accepted() {
+        // 1% confidence limit against a 100.00000000 reference, published 30s late
+        let price = oracle_price(100_0000_0000, 1_0000_0000, 1_030);
+        assert!(check_oracle_price(&price, -8, 1_000, 60, 100, 100_0000_0000).is_ok());
+        // Both ends of the window are inclusive
+        for publish_time in [1_000, 1_060] {
+            let price = oracle_price(100_0000_0000, 0, publish_time);
+            assert!(check_oracle_price(&price, -8, 1_000, 60, 100, 100_0000_0000).is_ok());
+        }
+    }
+
+    #[test]
+    fn oracle_price_rejects_wrong_exponent() {
+        let price = oracle_price(100_0000_0000, 0, 1_000);
+        let err = check_oracle_price(&price, -6, 1_000, 60, 100, 100_0000_0000).unwrap_err();
+        assert_eq!(err, ErrorCode::OracleExponentMismatch.into());
+    }
+
+    #[test]
+    fn oracle_price_rejects_stale_publish_time() {
+        for publish_time in [1_061, i64::MAX] {
+            let price = oracle_price(100_0000_0000, 0, publish_time);
+            let err = check_oracle_price(&price, -8, 1_000, 60, 100, 100_0000_0000).unwrap_err();
+            assert_eq!(err, ErrorCode::OraclePriceStale.into());
+        }
+    }
+
+    #[test]
+    fn oracle_price_rejects_publication_before_resolution() {
+        for publish_time in [999, 940, i64::MIN] {
+            let price = oracle_price(100_0000_0000, 0, publish_time);
+            let err = check_oracle_price(&price, -8, 1_000, 60, 100, 100_0000_0000).unwrap_err();
+            assert_eq!(err, ErrorCode::OraclePriceBeforeResolution.into());
+        }
+    }
+
+    #[test]
+    fn oracle_price_rejects_wide_confidence() {
+        let price = oracle_price(100_0000_0000, 1_0000_0001, 1_000);
+        let err = check_oracle_price(&price, -8, 1_000, 60, 100, 100_0000_0000).unwrap_err();
+        assert_eq!(err, ErrorCode::OracleConfidenceTooWide.into());
+        // A zero reference tolerates no uncertainty at all
+        let price = oracle_price(0, 1, 1_000);
+        let err = check_oracle_price(&price, -8, 1_000, 60, 100, 0).unwrap_err();
+        assert_eq!(err, ErrorCode::OracleConfidenceTooWide.into());
+    }
+
+    fn pyth_account(status: u32) -> Vec<u8> {
+        let mut data = vec![0u8; 240];
+        data[0..4].copy_from_slice(&PYTH_MAGIC.to_le_bytes());
+        data[4..8].copy_from_slice(&PYTH_VERSION_2.to_le_bytes());
+        data[8..12].copy_from_slice(&PYTH_ACCOUNT_TYPE_PRICE.to_le_bytes());
+        data[20..24].copy_from_slice(&(-8i32).to_le_bytes());
+        data[96..104].copy_from_slice(&1_700_000_000i64.to_le_bytes());
+        data[208..216].copy_from_slice(&6_500_000_000_000i64.to_le_bytes());
+        data[216..224].copy_from_slice(&2_500_000_000u64.to_le_bytes());
+        data[224..228].copy_from_slice(&status.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn pyth_adapter_reads_aggregate_price() {
+        let data = pyth_account(PYTH_STATUS_TRADING);
+        assert_eq!(
+            PythAdapter { data: &data }.read_price().unwrap(),
+            OraclePrice {
+                price: 6_500_000_000_000,
+                conf: 2_500_000_000,
+                expo: -8,
+                publish_time: 1_700_000_000,
+            }
+        );
+    }
+
+    #[test]
+    fn pyth_adapter_rejects_bad_accounts() {
+        let halted = pyth_account(0);
+        let err = PythAdapter { data: &halted }.read_price().unwrap_err();
+        assert_eq!(err, ErrorCode::OraclePriceUnavailable.into());
+
+        let mut wrong_magic = pyth_account(PYTH_STATUS_TRADING);
+        wrong_magic[0] ^= 0xff;
+        let err = PythAdapter { data: &wrong_magic }.read_price().unwrap_err();
+        assert_eq!(err, ErrorCode::InvalidOracleAccount.into());
+
+        let truncated = &pyth_account(PYTH_STATUS_TRADING)[..200];
+        let err = PythAdapter { data: truncated }.read_price().unwrap_err();
+        assert_eq!(err, ErrorCode::InvalidOracleAccount.into());
+    }
+
+    #[test]
+    fn pyth_source_reads_only_the_market_price_account() {
+        let (key, owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (mut lamports, mut data) = (0, pyth_account(PYTH_STATUS_TRADING));
+        let account = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+
+        let source = OracleSource::Pyth { price_account: key };
+        let price = source.read_price(Some(&account), None, 1_700_000_000).unwrap();
+        assert_eq!(price.price, 6_500_000_000_000);
+
+        let other = OracleSource::Pyth { price_account: Pubkey::new_unique() };
+        let err = other.read_price(Some(&account), None, 1_700_000_000).unwrap_err();
+        assert_eq!(err, ErrorCode::InvalidOracleAccount.into());
+        let err = source.read_price(None, None, 1_700_000_000).unwrap_err();
+        assert_eq!(err, ErrorCode::InvalidOracleAccount.into());
+    }
+
+    #[test]
+    fn pyth_price_settles_binary_market_against_threshold() {
+        let data = pyth_account(PYTH_STATUS_TRADING);
+        let price = PythAdapter { data: &data }.read_price().unwrap();
+        let mut market = test_market();
+
+        // Long needs the price strictly above the threshold
+        for (threshold, winner) in [
+            (6_499_999_999_999, Direction::Long),
+            (6_500_000_000_000, Direction::Short),
+            (6_600_000_000_000, Direction::Short),
+        ] {
+            market.threshold_price = threshold;
+            assert!(market.validate_settlement_price(&price).is_ok());
+            assert_eq!(market.outcome_for_price(price.price).unwrap(), Some(winner.outcome()));
+        }
+
+        // 25.00000000 confidence is 0.04% of the price, over a 0.01% limit
+        market.max_confidence_bps = 1;
+        let err = market.validate_settlement_price(&price).unwrap_err();
+        assert_eq!(err, ErrorCode::OracleConfidenceTooWide.into());
+    }
+
+    fn price_update_account(verification_level: &[u8], feed_id: [u8; 32]) -> Vec<u8> {
+        price_update_account_at(verification_level, feed_id, 1_700_000_000, 1_699_999_999)
+    }
+
+    fn price_update_account_at(
+        verification_level: &[u8],
+        feed_id: [u8; 32],
+        publish_time: i64,
+        prev_publish_time: i64,
+    ) -> Vec<u8> {
+        let mut data = PYTH_PRICE_UPDATE_DISCRIMINATOR.to_vec();
+        data.extend_from_slice(Pubkey::new_unique().as_ref());
+        data.extend_from_slice(verification_level);
+        data.extend_from_slice(&feed_id);
+        data.extend_from_slice(&6_500_000_000_000i64.to_le_bytes());
+        data.extend_from_slice(&2_500_000_000u64.to_le_bytes());
+        data.extend_from_slice(&(-8i32).to_le_bytes());
+        data.extend_from_slice(&publish_time.to_le_bytes());
+        data.extend_from_slice(&prev_publish_time.to_le_bytes());
+        data.extend_from_slice(&[0u8; 32]); // EMA price and conf, posted_slot
+        data
+    }
+
+    #[test]
+    fn pyth_receiver_program_id_matches_address() {
+        assert_eq!(
+            PYTH_RECEIVER_PROGRAM_ID.to_string(),
+            "rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ"
+        );
+    }
+
+    #[test]
+    fn pyth_pull_adapter_reads_verified_update() {
+        let data = price_update_account(&[PYTH_VERIFICATION_FULL], [7; 32]);
+        let adapter = PythPullAdapter {
+            data: &data,
+            owner: &PYTH_RECEIVER_PROGRAM_ID,
+            feed_id: &[7; 32],
+            resolution_time: 1_700_000_000,
+        };
+        assert_eq!(
+            adapter.read_price().unwrap(),
+            OraclePrice {
+                price: 6_500_000_000_000,
+                conf: 2_500_000_000,
+                expo: -8,
+                publish_time: 1_700_000_000,
+            }
+        );
+    }
+
+    #[test]
+    fn pyth_pull_adapter_rejects_unverified_or_foreign_updates() {
+        let full = price_update_account(&[PYTH_VERIFICATION_FULL], [7; 32]);
+        let partial = price_update_account(&[0, 5], [7; 32]);
+
+        let err = PythPullAdapter { data: &partial, owner: &PYTH_RECEIVER_PROGRAM_ID, feed_id: &[7; 32], resolution_time: 1_700_000_000 }
+            .read_price()
+            .unwrap_err();
+        assert_eq!(err, ErrorCode::OracleNotFullyVerified.into());
+
+        let err = PythPullAdapter { data: &full, owner: &PYTH_RECEIVER_PROGRAM_ID, feed_id: &[8; 32], resolution_time: 1_700_000_000 }
+            .read_price()
+            .unwrap_err();
+        assert_eq!(err, ErrorCode::InvalidOracleAccount.into());
+
+        let err = PythPullAdapter { data: &full, owner: &crate::ID, feed_id: &[7; 32], resolution_time: 1_700_000_000 }
+            .read_price()
+            .unwrap_err();
+        assert_eq!(err, ErrorCode::InvalidOracleAccount.into());
+    }
+
+    #[test]
+    fn pyth_pull_adapter_accepts_only_the_update_spanning_resolution() {
+        let read = |publish_time, prev_publish_time| {
+            let data = price_update_account_at(&[PYTH_VERIFICATION_FULL], [7; 32], publish_time, prev_publish_time);
+            PythPullAdapter {
+                data: &data,
+                owner: &PYTH_RECEIVER_PROGRAM_ID,
+                feed_id: &[7; 32],
+                resolution_time: 1_000,
+            }
+            .read_price()
+        };
+
+        assert_eq!(read(1_000, 999).unwrap().publish_time, 1_000);
+        assert_eq!(read(1_030, 990).unwrap().publish_time, 1_030);
+        // Later updates, and updates from before resolution, are rejected
+        for (publish_time, prev_publish_time) in [(1_031, 1_000), (1_031, 1_030), (999, 998)] {
+            assert_eq!(
+                read(publish_time, prev_publish_time).unwrap_err(),
+                ErrorCode::OracleUpdateNotAtResolution.into()
+            );
+        }
+    }
+
+    fn switchboard_account(num_success: u32, min_results: u32) -> Vec<u8> {
+        let mut data = vec![0u8; 512];
+        data[0..8].copy_from_slice(&SWITCHBOARD_AGGREGATOR_DISCRIMINATOR);
+        data[SWITCHBOARD_MIN_ORACLE_RESULTS..][..4].copy_from_slice(&min_results.to_le_bytes());
+        data[SWITCHBOARD_ROUND_NUM_SUCCESS..][..4].copy_from_slice(&num_success.to_le_bytes());
+        data[SWITCHBOARD_ROUND_OPEN_TIMESTAMP..][..8].copy_from_slice(&1_700_000_000i64.to_le_bytes());
+        // 65_000.12345678 with scale 8, std deviation 0.0000000015 with scale 10
+        data[SWITCHBOARD_ROUND_RESULT..][..16].copy_from_slice(&6_500_012_345_678i128.to_le_bytes());
+        data[SWITCHBOARD_ROUND_RESULT + 16..][..4].copy_from_slice(&8u32.to_le_bytes());
+        data[SWITCHBOARD_ROUND_STD_DEVIATION..][..16].copy_from_slice(&15i128.to_le_bytes());
+        data[SWITCHBOARD_ROUND_STD_DEVIATION + 16..][..4].copy_from_slice(&10u32.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn switchboard_adapter_reads_latest_round() {
+        let data = switchboard_account(3, 2);
+        assert_eq!(
+            SwitchboardAdapter { data: &data }.read_price().unwrap(),
+            OraclePrice {
+                price: 6_500_012_345_678,
+                conf: 1, // 0.15 at scale 8, rounded up
+                expo: -8,
+                publish_time: 1_700_000_000,
+            }
+        );
+    }
+
+    #[test]
+    fn switchboard_adapter_rejects_bad_accounts() {
+        let short_of_quorum = switchboard_account(1, 2);
+        let err = SwitchboardAdapter { data: &short_of_quorum }.read_price().unwrap_err();
+        assert_eq!(err, ErrorCode::OraclePriceUnavailable.into());
+
+        let no_results = switchboard_account(0, 0);
+        let err = SwitchboardAdapter { data: &no_results }.read_price().unwrap_err();
+        assert_eq!(err, ErrorCode::OraclePriceUnavailable.into());
+
+        let pyth = pyth_account(PYTH_STATUS_TRADING);
+        let err = SwitchboardAdapter { data: &pyth }.read_price().unwrap_err();
+        assert_eq!(err, ErrorCode::InvalidOracleAccount.into());
+    }
+
+    #[test]
+    fn rescale_rounds_confidence_up() {
+        assert_eq!(rescale_round_up(15, 10, 8), Some(1));
+        assert_eq!(rescale_round_up(100, 10, 8), Some(1));
+        assert_eq!(rescale_round_up(101, 10, 8), Some(2));
+        assert_eq!(rescale_round_up(7, 6, 8), Some(700));
+        assert_eq!(rescale_round_up(u128::MAX, 0, 8), None);
+    }
+
+    fn attestation(feed_id: [u8; 32], price: i64, publish_time: i64) -> Vec<u8> {
+        let mut message = ATTESTATION_DOMAIN.to_vec();
+        message.extend_from_slice(&feed_id);
+        message.extend_from_slice(&price.to_le_bytes());
+        message.extend_from_slice(&1_000u64.to_le_bytes());
+        message.extend_from_slice(&(-8i32).to_le_bytes());
+        message.extend_from_slice(&publish_time.to_le_bytes());
+        message
+    }
+
+    #[test]
+    fn attestation_adapter_reads_matching_feed() {
+        let messages = vec![
+            b"unrelated message".to_vec(),
+            attestation([2; 32], 111, 1_700_000_000),
+            attestation([1; 32], 6_500_000_000_000, 1_700_000_000),
+        ];
+        let read = |feed_id: &[u8; 32]| {
+            AttestationAdapter { messages: &messages, feed_id, resolution_time: 1_700_000_000 }.read_price()
+        };
+        assert_eq!(
+            read(&[1; 32]).unwrap(),
+            OraclePrice {
+                price: 6_500_000_000_000,
+                conf: 1_000,
+                expo: -8,
+                publish_time: 1_700_000_000,
+            }
+        );
+
+        let err = read(&[3; 32]).unwrap_err();
+        assert_eq!(err, ErrorCode::OraclePriceUnavailable.into());
+    }
+
+    #[test]
+    fn attestation_adapter_ignores_prices_from_other_times() {
+        // Earlier-listed prices published before or after resolution_time are skipped
+        let messages = vec![
+            attestation([1; 32], 100, 1_699_999_999),
+            attestation([1; 32], 200, 1_700_000_030),
+            attestation([1; 32], 300, 1_700_000_000),
+        ];
+        let adapter = AttestationAdapter { messages: &messages, feed_id: &[1; 32], resolution_time: 1_700_000_000 };
+        assert_eq!(adapter.read_price().unwrap().price, 300);
+
+        let adapter = AttestationAdapter { messages: &messages[..2], feed_id: &[1; 32], resolution_time: 1_700_000_000 };
+        assert_eq!(adapter.read_price().unwrap_err(), ErrorCode::OraclePriceUnavailable.into());
+    }
+
+    /// Ed25519 program instruction data with every key, signature and message inline
+    fn ed25519_instruction(entries: &[(Pubkey, &[u8])]) -> Vec<u8> {
+        let header = 2 + entries.len() * ED25519_OFFSETS_SIZE;
+        let mut offsets = vec![entries.len() as u8, 0];
+        let mut payload = Vec::new();
+        for (pubkey, message) in entries {
+            let pubkey_offset = header + payload.len();
+            payload.extend_from_slice(pubkey.as_ref());
+            let signature_offset = header + payload.len();
+            payload.extend_from_slice(&[0u8; 64]);
+            let message_offset = header + payload.len();
+            payload.extend_from_slice(message);
+            for field in [
+                signature_offset as u16,
+                u16::MAX,
+                pubkey_offset as u16,
+                u16::MAX,
+                message_offset as u16,
+                message.len() as u16,
+                u16::MAX,
+            ] {
+                offsets.extend_from_slice(&field.to_le_bytes());
+            }
+        }
+        offsets.extend_from_slice(&payload);
+        offsets
+    }
+
+    #[test]
+    fn ed25519_instruction_yields_keys_and_messages() {
+        let (alice, bob) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let data = ed25519_instruction(&[(alice, b"first"), (bob, b"second message")]);
+
+        assert_eq!(
+            parse_ed25519_instruction(&data).unwrap(),
+            vec![(alice, &b"first"[..]), (bob, &b"second message"[..])]
+        );
+    }
+
+    #[test]
+    fn ed25519_instruction_rejects_external_data() {
+        let mut data = ed25519_instruction(&[(Pubkey::new_unique(), b"message")]);
+        // Point the message at instruction 0 instead of the ed25519 instruction itself
+        data[2 + 12..2 + 14].copy_from_slice(&0u16.to_le_bytes());
+        let err = parse_ed25519_instruction(&data).unwrap_err();
+        assert_eq!(err, ErrorCode::InvalidSignatureInstruction.into());
+
+        let truncated = &ed25519_instruction(&[(Pubkey::new_unique(), b"message")])[..40];
+        let err = parse_ed25519_instruction(truncated).unwrap_err();
+        assert_eq!(err, ErrorCode::InvalidSignatureInstruction.into());
+    }
+
+    #[test]
+    fn next_round_follows_previous_by_one_interval() {
+        assert_eq!(next_round_time(1_000, 3_600, 0, 1_500).unwrap(), 4_600);
+        // First round of a series: previous is one interval before the first resolution time
+        assert_eq!(next_round_time(-2_600, 3_600, 0, 0).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn next_round_skips_missed_intervals() {
+        // Exactly on a slot boundary the round must still be strictly in the future
+        assert_eq!(next_round_time(1_000, 3_600, 0, 4_600).unwrap(), 8_200);
+        assert_eq!(next_round_time(1_000, 3_600, 0, 9_000).unwrap(), 11_800);
+        assert!(next_round_time(1_000, 0, 0, 9_000).is_err());
+        assert!(next_round_time(i64::MAX - 10, 3_600, 0, 0).is_err());
+    }
+
+    #[test]
+    fn next_round_skips_intervals_that_would_already_be_locked() {
+        // With a 300s lock buffer, the 4_600 round locks at 4_300
+        assert_eq!(next_round_time(1_000, 3_600, 300, 4_299).unwrap(), 4_600);
+        assert_eq!(next_round_time(1_000, 3_600, 300, 4_300).unwrap(), 8_200);
+        assert_eq!(next_round_time(1_000, 3_600, 300, 4_500).unwrap(), 8_200);
+        // A buffer spanning several intervals skips all of them
+        assert_eq!(next_round_time(1_000, 100, 350, 1_000).unwrap(), 1_400);
+        assert!(next_round_time(1_000, 3_600, i64::MAX, 1).is_err());
+    }
+
+    #[test]
+    fn series_market_ids_are_flagged_and_distinct() {
+        let (series, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let ids = [
+            series_market_id(&series, 0),
+            series_market_id(&series, 1),
+            series_market_id(&other, 0),
+        ];
+
+        assert!(ids.iter().all(|id| id & SERIES_MARKET_ID_FLAG != 0));
+        assert_ne!(ids[0], ids[1]);
+        assert_ne!(ids[0], ids[2]);
+        assert_eq!(series_market_id(&series, 1), ids[1]);
+    }
+
+    #[test]
+    fn reveal_message_binds_position_outcome_and_salt() {
+        let position = Pubkey::new_unique();
+        let message = reveal_message(&position, 1, &[9; 32]);
+
+        assert!(message.starts_with(REVEAL_DOMAIN));
+        assert_eq!(message.len(), REVEAL_DOMAIN.len() + 32 + 32 + 1 + 32);
+        assert_ne!(message, reveal_message(&Pubkey::new_unique(), 1, &[9; 32]));
+        assert_ne!(message, reveal_message(&position, 0, &[9; 32]));
+        assert_ne!(message, reveal_message(&position, 1, &[8; 32]));
+    }
+
+    #[test]
+    fn batched_reveal_signatures_are_found_per_owner() {
+        let (alice, bob) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (alice_position, bob_position) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let alice_reveal = reveal_message(&alice_position, 0, &[1; 32]);
+        let bob_reveal = reveal_message(&bob_position, 1, &[2; 32]);
+        let data = ed25519_instruction(&[(alice, &alice_reveal), (bob, &bob_reveal)]);
+
+        let signed_by = |owner: Pubkey| -> Vec<Vec<u8>> {
+            parse_ed25519_instruction(&data)
+                .unwrap()
+                .into_iter()
+                .filter(|(pubkey, _)| *pubkey == owner)
+                .map(|(_, message)| message.to_vec())
+                .collect()
+        };
+
+        assert_eq!(signed_by(alice), vec![alice_reveal.clone()]);
+        assert_eq!(signed_by(bob), vec![bob_reveal]);
+        assert!(!signed_by(bob).contains(&alice_reveal));
+    }
+
+    #[test]
+    fn stake_bounds_scale_with_decimals() {
+        // Native SOL: 0.01 - 100 SOL
+        assert_eq!(stake_bounds(SOL_DECIMALS, 1, 10_000).unwrap(), (10_000_000, 100_000_000_000));
+        // USDC (6 decimals): 0.01 - 100 USDC
+        assert_eq!(stake_bounds(6, 1, 10_000).unwrap(), (10_000, 100_000_000));
+        // Mints with no fractional units still require a non-zero stake
+        assert_eq!(stake_bounds(0, 1, 10_000).unwrap(), (1, 100));
+        // Bounds that do not fit in u64 base units are rejected
+        assert!(stake_bounds(18, 1, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn bps_rounds_down() {
+        assert_eq!(apply_bps(1_000_000, 250).unwrap(), 25_000);
+        assert_eq!(apply_bps(9_999, 1).unwrap(), 0);
+        assert_eq!(apply_bps(u64::MAX, 10_000).unwrap(), u64::MAX);
+    }
+}